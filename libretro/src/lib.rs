@@ -0,0 +1,441 @@
+use std::ffi::{CString, c_char, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chip8::{Chip8, beeper::Beeper, rom::RomLoader, savestate::STATE_SIZE};
+
+pub const RETRO_API_VERSION: c_uint = 1;
+
+pub const RETRO_DEVICE_JOYPAD: c_uint = 1;
+pub const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+pub const RETRO_REGION_NTSC: c_uint = 0;
+
+pub const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+pub const RETRO_ENVIRONMENT_GET_LOG_INTERFACE: c_uint = 27;
+pub const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+pub const RETRO_LOG_ERROR: c_uint = 3;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+pub type RetroEnvironment = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = unsafe extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = unsafe extern "C" fn();
+pub type RetroInputState = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+pub type RetroLogPrintf = unsafe extern "C" fn(level: c_uint, fmt: *const c_char, ...);
+
+#[repr(C)]
+pub struct RetroLogCallback {
+    pub log: Option<RetroLogPrintf>
+}
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char
+}
+
+// Joypad button ids (RETRO_DEVICE_ID_JOYPAD_*) and the CHIP-8 key each one presses.
+// The d-pad maps to 2/4/6/8 and A to 5, the layout most games use for movement
+pub const JOYPAD_MAP: [(c_uint, u8); 16] = [
+    (4, 0x2),  // UP
+    (5, 0x8),  // DOWN
+    (6, 0x4),  // LEFT
+    (7, 0x6),  // RIGHT
+    (8, 0x5),  // A
+    (0, 0x0),  // B
+    (9, 0x1),  // X
+    (1, 0x3),  // Y
+    (10, 0x7), // L
+    (11, 0x9), // R
+    (2, 0xA),  // SELECT
+    (3, 0xB),  // START
+    (12, 0xC), // L2
+    (13, 0xD), // R2
+    (14, 0xE), // L3
+    (15, 0xF), // R3
+];
+
+// Copied out before calling any of them, so that a frontend calling back into the core from one doesn't deadlock
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    log: Option<RetroLogPrintf>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>
+}
+
+struct Core {
+    chip: Chip8,
+    beeper: Beeper,
+    pressed: [bool; 16],
+    fault_logged: bool
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    log: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None
+});
+
+// Boxed so that the pointer handed out by retro_get_memory_data stays put
+static CORE: Mutex<Option<Box<Core>>> = Mutex::new(None);
+
+// Still usable after a caught panic, which drops the game anyway
+fn callbacks() -> MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn core() -> MutexGuard<'static, Option<Box<Core>>> {
+    CORE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Runs the body of an entry point, giving `fallback` instead of unwinding into the frontend
+fn guard<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        log(RETRO_LOG_ERROR, "Internal error, the game was stopped");
+        *core() = None;
+        fallback
+    })
+}
+
+// Through the frontend's log interface, a core has no console to write to
+fn log(level: c_uint, message: &str) {
+    let Some(log) = callbacks().log else { return };
+    // Passed as the format, so a '%' has to be escaped
+    let Ok(message) = CString::new(format!("{}\n", message.replace('%', "%%"))) else { return };
+    unsafe { log(level, message.as_ptr()) };
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_init() {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_deinit() {
+    guard((), || *core() = None)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_environment(cb: RetroEnvironment) {
+    guard((), || {
+        let mut interface = RetroLogCallback { log: None };
+        let has_log = unsafe { cb(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, &mut interface as *mut RetroLogCallback as *mut c_void) };
+        let mut callbacks = callbacks();
+        callbacks.environment = Some(cb);
+        callbacks.log = if has_log { interface.log } else { None };
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_video_refresh(cb: RetroVideoRefresh) {
+    guard((), || callbacks().video_refresh = Some(cb))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample(_cb: RetroAudioSample) {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample_batch(cb: RetroAudioSampleBatch) {
+    guard((), || callbacks().audio_sample_batch = Some(cb))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_poll(cb: RetroInputPoll) {
+    guard((), || callbacks().input_poll = Some(cb))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_state(cb: RetroInputState) {
+    guard((), || callbacks().input_state = Some(cb))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// # Safety
+/// `info` must point to a writable `RetroSystemInfo`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    guard((), || unsafe {
+        *info = RetroSystemInfo {
+            library_name: c"Chip8 Emulatxr".as_ptr(),
+            library_version: c"0.1.0".as_ptr(),
+            valid_extensions: c"ch8|c8".as_ptr(),
+            need_fullpath: false,
+            block_extract: false
+        };
+    })
+}
+
+/// # Safety
+/// `info` must point to a writable `RetroSystemAvInfo`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    guard((), || unsafe {
+        *info = RetroSystemAvInfo {
+            geometry: RetroGameGeometry {
+                base_width: 64,
+                base_height: 32,
+                max_width: 64,
+                max_height: 32,
+                aspect_ratio: 2.0
+            },
+            timing: RetroSystemTiming {
+                fps: 60.0,
+                sample_rate: SAMPLE_RATE as f64
+            }
+        };
+    })
+}
+
+/// # Safety
+/// `game` must be null or point to a valid `RetroGameInfo` whose `data` holds `size` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    guard(false, || unsafe { load_game(game) })
+}
+
+unsafe fn load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() { return false }
+    let (name, data) = unsafe {
+        let game = &*game;
+        if game.data.is_null() { return false }
+        let name = if game.path.is_null() {
+            String::new()
+        } else {
+            std::ffi::CStr::from_ptr(game.path).to_string_lossy().into_owned()
+        };
+        (name, std::slice::from_raw_parts(game.data as *const u8, game.size).to_vec())
+    };
+    let rom = match RomLoader::new().load_bytes(&name, data) {
+        Ok(rom) => rom,
+        Err(e) => {
+            log(RETRO_LOG_ERROR, &e.to_string());
+            return false;
+        }
+    };
+
+    let environment = callbacks().environment;
+    if let Some(environment) = environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        let accepted = unsafe { environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) };
+        if !accepted {
+            log(RETRO_LOG_ERROR, "The frontend does not support XRGB8888 pixels");
+            return false;
+        }
+    }
+
+    let mut chip = Chip8::new();
+    if let Err(e) = chip.insert_rom(rom.data) {
+        log(RETRO_LOG_ERROR, &e.to_string());
+        return false;
+    }
+    chip.load();
+
+    *core() = Some(Box::new(Core {
+        chip,
+        beeper: Beeper::new(SAMPLE_RATE),
+        pressed: [false; 16],
+        fault_logged: false
+    }));
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const RetroGameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_unload_game() {
+    guard((), || *core() = None)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_reset() {
+    guard((), || if let Some(core) = core().as_mut() {
+        core.chip.reset();
+        core.pressed = [false; 16];
+        core.fault_logged = false;
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_run() {
+    guard((), run)
+}
+
+// The core is only locked between calls to the frontend
+fn run() {
+    let callbacks = *callbacks();
+
+    let mut buttons = [false; 16];
+    if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state) {
+        unsafe { input_poll() };
+        for (down, (button, _)) in buttons.iter_mut().zip(JOYPAD_MAP) {
+            *down = unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, button) } != 0;
+        }
+    }
+
+    let mut framebuffer = [0u32; 64 * 32];
+    let mut audio = [0i16; SAMPLES_PER_FRAME * 2];
+    let mut fault = None;
+    {
+        let mut guard = core();
+        let Some(core) = guard.as_mut() else { return };
+
+        if callbacks.input_state.is_some() {
+            for (down, (_, key)) in buttons.into_iter().zip(JOYPAD_MAP) {
+                if down != core.pressed[key as usize] {
+                    core.pressed[key as usize] = down;
+                    if down { core.chip.key_down(key) } else { core.chip.key_up(key) }
+                }
+            }
+        }
+
+        core.chip.step_frame();
+
+        for (out, pixel) in framebuffer.iter_mut().zip(core.chip.display.screen.iter().flatten()) {
+            *out = if *pixel == 0 { 0x0000_0000 } else { 0x00FF_FFFF };
+        }
+
+        let tone = core.chip.cpu.sound > 0;
+        for frame in audio.chunks_exact_mut(2) {
+            let sample = if tone { (core.beeper.next_sample() * i16::MAX as f32) as i16 } else { 0 };
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+
+        // The game stays on screen, frozen where it stopped
+        if let Some(e) = core.chip.fault && !core.fault_logged {
+            core.fault_logged = true;
+            fault = Some(format!("Stopped: {} at {:#06x}", e, core.chip.cpu.read_pc()));
+        }
+    }
+
+    if let Some(fault) = fault {
+        log(RETRO_LOG_ERROR, &fault);
+    }
+    if let Some(video_refresh) = callbacks.video_refresh {
+        unsafe { video_refresh(framebuffer.as_ptr() as *const c_void, 64, 32, 64 * 4) };
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        unsafe { audio_sample_batch(audio.as_ptr(), SAMPLES_PER_FRAME) };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+/// `data` must point to at least `size` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    guard(false, || {
+        let guard = core();
+        let Some(core) = guard.as_ref() else { return false };
+        if data.is_null() || size < STATE_SIZE { return false }
+
+        let state = core.chip.save_state();
+        unsafe { std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len()) };
+        true
+    })
+}
+
+/// # Safety
+/// `data` must point to at least `size` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    guard(false, || {
+        let mut guard = core();
+        let Some(core) = guard.as_mut() else { return false };
+        if data.is_null() || size < STATE_SIZE { return false }
+
+        let state = unsafe { std::slice::from_raw_parts(data as *const u8, STATE_SIZE) };
+        match core.chip.load_state(state) {
+            Ok(()) => {
+                core.fault_logged = false;
+                true
+            }
+            Err(e) => {
+                drop(guard);
+                log(RETRO_LOG_ERROR, &e);
+                false
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    guard(std::ptr::null_mut(), || match (id, core().as_mut()) {
+        (RETRO_MEMORY_SYSTEM_RAM, Some(core)) => core.chip.memory.address_space.as_mut_ptr() as *mut c_void,
+        _ => std::ptr::null_mut()
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match id {
+        RETRO_MEMORY_SYSTEM_RAM => 0x1000,
+        _ => 0
+    }
+}
@@ -0,0 +1,308 @@
+// Loads the built core and drives it only through the symbols it exports, the same way a
+// libretro frontend would. The types below are written from libretro.h rather than taken
+// from the crate, so that they check its C interface instead of agreeing with it
+use std::ffi::{CStr, c_char, c_uint, c_void};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libloading::Library;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_LOG_INTERFACE: c_uint = 27;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const SAMPLES_PER_FRAME: usize = 44100 / 60;
+
+// Every function a libretro core has to export
+const API: [&str; 25] = [
+    "retro_api_version", "retro_init", "retro_deinit", "retro_set_environment",
+    "retro_set_video_refresh", "retro_set_audio_sample", "retro_set_audio_sample_batch",
+    "retro_set_input_poll", "retro_set_input_state", "retro_set_controller_port_device",
+    "retro_get_system_info", "retro_get_system_av_info", "retro_load_game",
+    "retro_load_game_special", "retro_unload_game", "retro_reset", "retro_run",
+    "retro_serialize_size", "retro_serialize", "retro_unserialize", "retro_cheat_reset",
+    "retro_cheat_set", "retro_get_region", "retro_get_memory_data", "retro_get_memory_size"
+];
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char
+}
+
+#[repr(C)]
+struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool
+}
+
+type Environment = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefresh = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleBatch = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPoll = unsafe extern "C" fn();
+type InputState = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+// Variadic in libretro.h, but the core passes its messages as the format, without arguments
+type LogPrintf = unsafe extern "C" fn(level: c_uint, fmt: *const c_char);
+
+// The entry points the tests use, looked up once in the loaded library
+struct Core {
+    library: Library,
+    api_version: unsafe extern "C" fn() -> c_uint,
+    init: unsafe extern "C" fn(),
+    deinit: unsafe extern "C" fn(),
+    set_environment: unsafe extern "C" fn(Environment),
+    set_video_refresh: unsafe extern "C" fn(VideoRefresh),
+    set_audio_sample_batch: unsafe extern "C" fn(AudioSampleBatch),
+    set_input_poll: unsafe extern "C" fn(InputPoll),
+    set_input_state: unsafe extern "C" fn(InputState),
+    get_system_info: unsafe extern "C" fn(*mut SystemInfo),
+    load_game: unsafe extern "C" fn(*const GameInfo) -> bool,
+    unload_game: unsafe extern "C" fn(),
+    run: unsafe extern "C" fn(),
+    serialize_size: unsafe extern "C" fn() -> usize,
+    serialize: unsafe extern "C" fn(*mut c_void, usize) -> bool,
+    unserialize: unsafe extern "C" fn(*const c_void, usize) -> bool,
+    get_memory_data: unsafe extern "C" fn(c_uint) -> *mut c_void
+}
+
+// Next to the test executable's directory, where cargo puts the cdylib it builds for the tests
+fn library_path() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let name = libloading::library_filename("chip8_libretro");
+    [deps.parent().unwrap(), deps].iter()
+        .map(|dir| dir.join(&name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| panic!("{} was not built", name.to_string_lossy()))
+}
+
+fn core() -> &'static Core {
+    static CORE: OnceLock<Core> = OnceLock::new();
+    CORE.get_or_init(|| unsafe {
+        let library = Library::new(library_path()).unwrap();
+        macro_rules! symbol {
+            ($name:literal) => {
+                *library.get(concat!($name, "\0").as_bytes()).expect(concat!($name, " is not exported"))
+            };
+        }
+        Core {
+            api_version: symbol!("retro_api_version"),
+            init: symbol!("retro_init"),
+            deinit: symbol!("retro_deinit"),
+            set_environment: symbol!("retro_set_environment"),
+            set_video_refresh: symbol!("retro_set_video_refresh"),
+            set_audio_sample_batch: symbol!("retro_set_audio_sample_batch"),
+            set_input_poll: symbol!("retro_set_input_poll"),
+            set_input_state: symbol!("retro_set_input_state"),
+            get_system_info: symbol!("retro_get_system_info"),
+            load_game: symbol!("retro_load_game"),
+            unload_game: symbol!("retro_unload_game"),
+            run: symbol!("retro_run"),
+            serialize_size: symbol!("retro_serialize_size"),
+            serialize: symbol!("retro_serialize"),
+            unserialize: symbol!("retro_unserialize"),
+            get_memory_data: symbol!("retro_get_memory_data"),
+            library
+        }
+    })
+}
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+static FRAMES: Mutex<Vec<Vec<u32>>> = Mutex::new(Vec::new());
+static SAMPLES: Mutex<usize> = Mutex::new(0);
+static HELD_BUTTON: Mutex<Option<c_uint>> = Mutex::new(None);
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// Set when the core could be called back from inside video_refresh
+static REENTERED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => (unsafe { *(data as *const c_uint) }) == RETRO_PIXEL_FORMAT_XRGB8888,
+        RETRO_ENVIRONMENT_GET_LOG_INTERFACE => {
+            unsafe { *(data as *mut Option<LogPrintf>) = Some(log) };
+            true
+        }
+        _ => false
+    }
+}
+
+unsafe extern "C" fn log(_level: c_uint, fmt: *const c_char) {
+    let message = unsafe { CStr::from_ptr(fmt) }.to_string_lossy().trim_end().replace("%%", "%");
+    MESSAGES.lock().unwrap().push(message);
+}
+
+unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    assert_eq!((width, height, pitch), (64, 32, 256));
+    let pixels = unsafe { std::slice::from_raw_parts(data as *const u32, 64 * 32) };
+    FRAMES.lock().unwrap().push(pixels.to_vec());
+    // Frontends may look at the memory while they draw
+    if !unsafe { (core().get_memory_data)(RETRO_MEMORY_SYSTEM_RAM) }.is_null() {
+        REENTERED.store(true, Ordering::SeqCst);
+    }
+}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    *SAMPLES.lock().unwrap() += frames;
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    (port == 0 && device == RETRO_DEVICE_JOYPAD && *HELD_BUTTON.lock().unwrap() == Some(id)) as i16
+}
+
+fn try_load(rom: &[u8]) -> bool {
+    let core = core();
+    unsafe {
+        (core.set_environment)(environment);
+        (core.set_video_refresh)(video_refresh);
+        (core.set_audio_sample_batch)(audio_sample_batch);
+        (core.set_input_poll)(input_poll);
+        (core.set_input_state)(input_state);
+        (core.init)();
+    }
+
+    FRAMES.lock().unwrap().clear();
+    *SAMPLES.lock().unwrap() = 0;
+    *HELD_BUTTON.lock().unwrap() = None;
+    MESSAGES.lock().unwrap().clear();
+    REENTERED.store(false, Ordering::SeqCst);
+
+    let game = GameInfo {
+        path: std::ptr::null(),
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        meta: std::ptr::null()
+    };
+    unsafe { (core.load_game)(&game) }
+}
+
+fn load(rom: &[u8]) {
+    assert!(try_load(rom));
+}
+
+fn run() {
+    unsafe { (core().run)() }
+}
+
+fn close() {
+    unsafe {
+        (core().unload_game)();
+        (core().deinit)();
+    }
+}
+
+#[test]
+fn exports_the_libretro_api() {
+    let library = &core().library;
+    for name in API {
+        let symbol = format!("{}\0", name);
+        assert!(unsafe { library.get::<unsafe extern "C" fn()>(symbol.as_bytes()) }.is_ok(), "{} is not exported", name);
+    }
+
+    let mut info = SystemInfo {
+        library_name: std::ptr::null(),
+        library_version: std::ptr::null(),
+        valid_extensions: std::ptr::null(),
+        need_fullpath: true,
+        block_extract: true
+    };
+    unsafe { (core().get_system_info)(&mut info) };
+    let extensions = unsafe { std::ffi::CStr::from_ptr(info.valid_extensions) };
+    assert_eq!(extensions.to_str().unwrap(), "ch8|c8");
+    assert!(!info.need_fullpath);
+}
+
+#[test]
+fn runs_a_frame() {
+    let _guard = LOCK.lock().unwrap();
+    // LD I, 0x0000; DRW V0, V0, 5; JP 0x0204
+    load(&[0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04]);
+
+    assert_eq!(unsafe { (core().api_version)() }, RETRO_API_VERSION);
+    run();
+
+    let frames = FRAMES.lock().unwrap();
+    assert_eq!(frames.len(), 1);
+    // Top row of the "0" sprite: 0xF0
+    assert_eq!(&frames[0][0..5], &[0x00FF_FFFF, 0x00FF_FFFF, 0x00FF_FFFF, 0x00FF_FFFF, 0]);
+    assert_eq!(*SAMPLES.lock().unwrap(), SAMPLES_PER_FRAME);
+    assert!(REENTERED.load(Ordering::SeqCst));
+
+    close();
+}
+
+#[test]
+fn joypad_reaches_keypad() {
+    let _guard = LOCK.lock().unwrap();
+    // LD V0, K; LD F, V0; DRW V1, V1, 5; JP 0x0206
+    load(&[0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06]);
+
+    run();
+    *HELD_BUTTON.lock().unwrap() = Some(8); // A, mapped to key 5
+    run();
+    run();
+
+    let frames = FRAMES.lock().unwrap();
+    // Top row of the "5" sprite: 0xF0
+    assert_eq!(&frames[2][0..5], &[0x00FF_FFFF, 0x00FF_FFFF, 0x00FF_FFFF, 0x00FF_FFFF, 0]);
+
+    close();
+}
+
+#[test]
+fn rejects_oversized_rom() {
+    let _guard = LOCK.lock().unwrap();
+    assert!(!try_load(&[0; 0x1000]));
+    assert_eq!(*MESSAGES.lock().unwrap(), ["The ROM is 4096 bytes, but at most 3584 bytes fit in memory"]);
+    assert!(!try_load(&[]));
+    assert_eq!(*MESSAGES.lock().unwrap(), ["The ROM is empty"]);
+    unsafe { (core().deinit)() };
+}
+
+#[test]
+fn faulting_rom_keeps_the_frontend_running() {
+    let _guard = LOCK.lock().unwrap();
+    // SYS 0x0123, which only the original hardware could run
+    load(&[0x01, 0x23]);
+
+    run();
+    run();
+    assert_eq!(FRAMES.lock().unwrap().len(), 2);
+    assert_eq!(*MESSAGES.lock().unwrap(), ["Stopped: Unknown opcode 0x0123 at 0x0200"]);
+
+    close();
+}
+
+#[test]
+fn serialize_roundtrip() {
+    let _guard = LOCK.lock().unwrap();
+    // ADD V0, 1; JP 0x0200
+    load(&[0x70, 0x01, 0x12, 0x00]);
+
+    run();
+    let core = core();
+    let size = unsafe { (core.serialize_size)() };
+    let mut state = vec![0u8; size];
+    assert!(unsafe { (core.serialize)(state.as_mut_ptr() as *mut c_void, size) });
+
+    run();
+    run();
+    assert!(unsafe { (core.unserialize)(state.as_ptr() as *const c_void, size) });
+
+    let mut again = vec![0u8; size];
+    assert!(unsafe { (core.serialize)(again.as_mut_ptr() as *mut c_void, size) });
+    assert_eq!(state, again);
+
+    close();
+}
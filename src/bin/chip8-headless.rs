@@ -0,0 +1,107 @@
+// Runs a ROM without any window, for a fixed number of frames, feeding input from
+// a movie file and optionally writing the output to a Y4M video and a WAV track
+use std::fs::File;
+use std::io::BufWriter;
+
+use chip8::{Chip8, avrecord::{AvRecorder, SAMPLE_RATE, WavWriter, Y4mWriter}, movie::Movie, rom::RomLoader, stack::{StackStorage, DEFAULT_STACK_DEPTH, MAX_STACK_DEPTH}, font::{Font, FontSet, DEFAULT_FONT_BASE}, timing::Timing};
+
+const USAGE: &str = "Usage: chip8-headless <rom> [--frames N] [--movie FILE] [--y4m FILE] [--wav FILE] [--scale N] [--seed N] [--display-wait] [--wait-for-release] [--timing fixed|vip] [--cycles-per-frame N] [--stack-depth N] [--stack memory|isolated] [--font vip|dream6800|eti660|schip] [--font-file FILE] [--font-base ADDR]";
+
+fn value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+fn number<T: std::str::FromStr>(args: &[String], name: &str, default: T) -> Result<T, String> {
+    match value(args, name) {
+        Some(text) => text.parse().map_err(|_| format!("Invalid value for {}: {}", name, text)),
+        None => Ok(default)
+    }
+}
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args[1].starts_with("--") {
+        return Err(USAGE.to_owned());
+    }
+
+    let rom = RomLoader::new().load_file(&args[1]).map_err(|e| e.to_string())?;
+    let movie = match value(&args, "--movie") {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read movie: {}", e))?;
+            Some(Movie::parse(&text)?)
+        },
+        None => None
+    };
+    // Without an explicit length, run one second past the last input
+    let default_frames = movie.as_ref().map(|movie| movie.last_frame() + 60).unwrap_or(600);
+    let frames = number(&args, "--frames", default_frames)?;
+    let scale = number(&args, "--scale", 10usize)?;
+
+    let video = match value(&args, "--y4m") {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+            Some(Y4mWriter::new(BufWriter::new(file), scale, [255, 255, 255], [0, 0, 0]).map_err(|e| e.to_string())?)
+        },
+        None => None
+    };
+    let audio = match value(&args, "--wav") {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Could not create {}: {}", path, e))?;
+            Some(WavWriter::new(BufWriter::new(file), SAMPLE_RATE).map_err(|e| e.to_string())?)
+        },
+        None => None
+    };
+    let mut recorder = AvRecorder::new(video, audio);
+
+    let mut chip = Chip8::new();
+    // Fixed seed by default so that runs can be compared
+    chip.seed_rng(number(&args, "--seed", 1u32)?);
+    chip.cycles_per_frame = number(&args, "--cycles-per-frame", chip8::DEFAULT_CYCLES_PER_FRAME)?.max(1);
+    chip.stack.depth = number(&args, "--stack-depth", DEFAULT_STACK_DEPTH)?;
+    if !(1..=MAX_STACK_DEPTH).contains(&chip.stack.depth) {
+        return Err(format!("Stack depth must be between 1 and {}", MAX_STACK_DEPTH));
+    }
+    if let Some(name) = value(&args, "--stack") {
+        chip.stack.storage = StackStorage::from_name(name).ok_or("Unknown stack, expected memory or isolated")?;
+    }
+    let mut font = Font::default();
+    if let Some(name) = value(&args, "--font") {
+        font = FontSet::from_name(name).ok_or("Unknown font, expected vip, dream6800, eti660 or schip")?.font();
+    }
+    if let Some(path) = value(&args, "--font-file") {
+        font = Font::from_file(std::path::Path::new(path))?;
+    }
+    let font_base = match value(&args, "--font-base") {
+        Some(text) => u16::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid value for --font-base: {}", text))?,
+        None => DEFAULT_FONT_BASE
+    };
+    chip.set_font(font, font_base)?;
+    chip.quirks.display_wait = args.iter().any(|arg| arg == "--display-wait");
+    chip.quirks.wait_for_release = args.iter().any(|arg| arg == "--wait-for-release");
+    if let Some(name) = value(&args, "--timing") {
+        chip.timing = Timing::from_name(name).ok_or("Unknown timing, expected fixed or vip")?;
+    }
+    chip.insert_rom(rom.data).map_err(|e| e.to_string())?;
+    chip.load();
+
+    for frame in 0..frames {
+        if let Some(movie) = &movie {
+            movie.apply(frame, &mut chip);
+        }
+        if chip.exit || chip.fault.is_some() { break }
+        chip.step_frame();
+        recorder.record_frame(&chip).map_err(|e| e.to_string())?;
+    }
+    recorder.finish().map_err(|e| e.to_string())?;
+    match chip.fault {
+        Some(fault) => Err(format!("{} at {:#06x}", fault, chip.cpu.read_pc())),
+        None => Ok(())
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use super::{Chip8, beeper::Beeper, frontend::VideoSink};
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+// Uncompressed YUV4MPEG2 stream, 4:4:4 so that any scale works
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    scale: usize,
+    on: [u8; 3],
+    off: [u8; 3]
+}
+
+impl<W: Write> Y4mWriter<W> {
+
+    pub fn new(mut out: W, scale: usize, on: [u8; 3], off: [u8; 3]) -> io::Result<Self> {
+        let scale = scale.max(1);
+        writeln!(out, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", 64 * scale, 32 * scale)?;
+        Ok(Y4mWriter { out, scale, on, off })
+    }
+
+    pub fn write_frame(&mut self, screen: &[[u8; 64]; 32]) -> io::Result<()> {
+        let (on, off) = (to_ycbcr(self.on), to_ycbcr(self.off));
+        let width = 64 * self.scale;
+
+        self.out.write_all(b"FRAME\n")?;
+        let mut row = vec![0u8; width];
+        for plane in 0..3 {
+            for line in screen.iter() {
+                for (x, value) in row.iter_mut().enumerate() {
+                    *value = if line[x / self.scale] != 0 { on[plane] } else { off[plane] };
+                }
+                for _ in 0..self.scale {
+                    self.out.write_all(&row)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// BT.601 studio range (Y 16-235, Cb and Cr 16-240), which is what players assume for Y4M
+fn to_ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + 0.256788 * r + 0.504129 * g + 0.097906 * b;
+    let cb = 128.0 - 0.148223 * r - 0.290993 * g + 0.439216 * b;
+    let cr = 128.0 + 0.439216 * r - 0.367788 * g - 0.071427 * b;
+    [y.round().clamp(0.0, 255.0) as u8, cb.round().clamp(0.0, 255.0) as u8, cr.round().clamp(0.0, 255.0) as u8]
+}
+
+// 16 bit mono PCM, the sizes in the header are filled in by finish
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples: u32
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;                 // PCM
+        out.write_all(&1u16.to_le_bytes())?;                 // mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * 2).to_le_bytes())?;    // byte rate
+        out.write_all(&2u16.to_le_bytes())?;                 // block align
+        out.write_all(&16u16.to_le_bytes())?;                // bits per sample
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples * 2;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Records every presented frame and the beeper output over the same frame
+pub struct AvRecorder<V: Write, A: Write + Seek> {
+    video: Option<Y4mWriter<V>>,
+    audio: Option<WavWriter<A>>,
+    beeper: Beeper,
+    samples: [i16; SAMPLES_PER_FRAME],
+    pub error: Option<io::Error>
+}
+
+impl<V: Write, A: Write + Seek> AvRecorder<V, A> {
+
+    pub fn new(video: Option<Y4mWriter<V>>, audio: Option<WavWriter<A>>) -> Self {
+        AvRecorder {
+            video,
+            audio,
+            beeper: Beeper::new(SAMPLE_RATE),
+            samples: [0; SAMPLES_PER_FRAME],
+            error: None
+        }
+    }
+
+    pub fn record_frame(&mut self, chip: &Chip8) -> io::Result<()> {
+        if let Some(video) = &mut self.video {
+            video.write_frame(&chip.display.screen)?;
+        }
+        if let Some(audio) = &mut self.audio {
+            let tone = chip.cpu.sound > 0;
+            for sample in self.samples.iter_mut() {
+                *sample = if tone { (self.beeper.next_sample() * i16::MAX as f32) as i16 } else { 0 };
+            }
+            audio.write_samples(&self.samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if let Some(video) = self.video {
+            video.finish()?;
+        }
+        if let Some(audio) = self.audio {
+            audio.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: Write, A: Write + Seek> VideoSink for AvRecorder<V, A> {
+    fn present(&mut self, chip: &Chip8) {
+        if self.error.is_some() { return }
+        if let Err(e) = self.record_frame(chip) {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod avrecord_test {
+    use std::io::Cursor;
+
+    #[test]
+    fn y4m_frame_layout() {
+        let mut screen = [[0u8; 64]; 32];
+        screen[0][0] = 1;
+
+        let mut writer = super::Y4mWriter::new(Vec::new(), 2, [255, 255, 255], [0, 0, 0]).unwrap();
+        writer.write_frame(&screen).unwrap();
+        let out = writer.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\nFRAME\n";
+        assert_eq!(&out[..header.len()], header);
+        let planes = &out[header.len()..];
+        assert_eq!(planes.len(), 128 * 64 * 3);
+        // Luma of the lit pixel, doubled in both directions, white and black in studio range
+        assert_eq!(&planes[0..3], &[235, 235, 16]);
+        assert_eq!(&planes[128..131], &[235, 235, 16]);
+        // Chroma of white and black is neutral
+        assert_eq!(planes[128 * 64], 128);
+    }
+
+    #[test]
+    fn wav_header_sizes() {
+        let mut writer = super::WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        writer.write_samples(&[0, 1, -1]).unwrap();
+        let out = writer.finish().unwrap().into_inner();
+
+        assert_eq!(out.len(), 44 + 6);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes([out[4], out[5], out[6], out[7]]), 36 + 6);
+        assert_eq!(u32::from_le_bytes([out[40], out[41], out[42], out[43]]), 6);
+        assert_eq!(&out[44..46], &[0, 0]);
+        assert_eq!(&out[48..50], &[0xFF, 0xFF]);
+    }
+}
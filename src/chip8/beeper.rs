@@ -0,0 +1,45 @@
+// Square wave generator for the sound timer, shared by every audio backend
+pub struct Beeper {
+    pub frequency: f32,
+    pub volume: f32,
+    sample_rate: f32,
+    phase: f32
+}
+
+impl Beeper {
+
+    pub fn new(sample_rate: u32) -> Self {
+        Beeper {
+            frequency: 440.0,
+            volume: 0.25,
+            sample_rate: sample_rate as f32,
+            phase: 0.0
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let sample = if self.phase < 0.5 { self.volume } else { -self.volume };
+        self.phase = (self.phase + self.frequency / self.sample_rate) % 1.0;
+        sample
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+#[cfg(test)]
+mod beeper_test {
+
+    #[test]
+    fn square_wave_period() {
+        let mut beeper = super::Beeper::new(8);
+        beeper.frequency = 2.0;
+
+        let mut out = [0.0f32; 8];
+        beeper.fill(&mut out);
+        assert_eq!(out, [0.25, 0.25, -0.25, -0.25, 0.25, 0.25, -0.25, -0.25]);
+    }
+}
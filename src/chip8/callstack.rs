@@ -0,0 +1,47 @@
+use super::{Chip8, stack::MAX_STACK_DEPTH};
+
+// One level of the call stack, worked out from the return address the CALL pushed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CallFrame {
+    pub call_site: u16,
+    // Read back from the CALL instruction, so only right if the code was not changed since
+    pub target: u16,
+    pub return_address: u16
+}
+
+impl Chip8 {
+
+    // Outermost call first
+    pub fn call_stack(&self) -> Vec<CallFrame> {
+        (0..self.stack_depth().min(MAX_STACK_DEPTH)).map(|level| {
+            let return_address = self.stack_entry(level);
+            let call_site = return_address.wrapping_sub(2) & 0x0FFF;
+            let memory = &self.memory.address_space;
+            let opcode = u16::from_be_bytes([memory[call_site as usize], memory[(call_site as usize + 1) & 0x0FFF]]);
+            CallFrame { call_site, target: opcode & 0x0FFF, return_address }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod callstack_test {
+    use super::super::Chip8;
+    use super::CallFrame;
+
+    #[test]
+    fn frames_from_nested_calls() {
+        // CALL 0x0206; JP 0x0202; (padding); CALL 0x020A; (padding); JP 0x020A
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x22, 0x0A, 0x00, 0x00, 0x12, 0x0A]).unwrap();
+        chip.load();
+        assert_eq!(chip.stack_depth(), 0);
+
+        chip.cycle();
+        chip.cycle();
+        assert_eq!(chip.stack_depth(), 2);
+        assert_eq!(chip.call_stack(), vec![
+            CallFrame { call_site: 0x0200, target: 0x0206, return_address: 0x0202 },
+            CallFrame { call_site: 0x0206, target: 0x020A, return_address: 0x0208 }
+        ]);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::Path;
+
+use image::{Delay, Frame, ImageResult, Rgba, RgbaImage, codecs::gif::{GifEncoder, Repeat}};
+
+pub fn render(screen: &[[u8; 64]; 32], scale: u32, on: [u8; 3], off: [u8; 3]) -> RgbaImage {
+    let scale = scale.max(1);
+    RgbaImage::from_fn(64 * scale, 32 * scale, |x, y| {
+        let [r, g, b] = if screen[(y / scale) as usize][(x / scale) as usize] != 0 { on } else { off };
+        Rgba([r, g, b, 255])
+    })
+}
+
+pub fn save_screenshot<P: AsRef<Path>>(screen: &[[u8; 64]; 32], scale: u32, on: [u8; 3], off: [u8; 3], path: P) -> ImageResult<()> {
+    render(screen, scale, on, off).save(path)
+}
+
+// Collects one screen per 60 Hz frame and writes them as an animated GIF
+pub struct Recorder {
+    pub scale: u32,
+    pub on: [u8; 3],
+    pub off: [u8; 3],
+    // Each distinct screen along with how many frames it stayed up
+    frames: Vec<([[u8; 64]; 32], u32)>
+}
+
+impl Recorder {
+
+    pub fn new(scale: u32, on: [u8; 3], off: [u8; 3]) -> Self {
+        Recorder { scale, on, off, frames: vec![] }
+    }
+
+    pub fn push(&mut self, screen: &[[u8; 64]; 32]) {
+        match self.frames.last_mut() {
+            Some((last, count)) if last == screen => *count += 1,
+            _ => self.frames.push((*screen, 1))
+        }
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frames.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn finish<P: AsRef<Path>>(self, path: P) -> ImageResult<()> {
+        let file = std::fs::File::create(path)?;
+        let mut encoder = GifEncoder::new(std::io::BufWriter::new(file));
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        // GIF delays are in hundredths of a second, so each delay is rounded from
+        // the total elapsed time to keep the drift from adding up
+        let mut elapsed = 0;
+        for (screen, count) in self.frames {
+            let start = elapsed * 100 / 60;
+            elapsed += count;
+            let end = elapsed * 100 / 60;
+
+            let delay = Delay::from_numer_denom_ms((end - start).max(1) * 10, 1);
+            encoder.encode_frame(Frame::from_parts(render(&screen, self.scale, self.on, self.off), 0, 0, delay))?;
+        }
+        Ok(())
+    }
+}
+
+// File name for a new capture, unique to the second
+pub fn timestamped_name(prefix: &str, extension: &str) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    format!("{}-{}.{}", prefix, secs, extension)
+}
+
+#[cfg(test)]
+mod capture_test {
+
+    #[test]
+    fn render_scales_pixels() {
+        let mut screen = [[0u8; 64]; 32];
+        screen[1][2] = 1;
+
+        let image = super::render(&screen, 3, [255, 0, 0], [0, 0, 255]);
+        assert_eq!(image.dimensions(), (192, 96));
+        assert_eq!(image.get_pixel(6, 3).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(8, 5).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(9, 3).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn recorder_merges_identical_frames() {
+        let mut recorder = super::Recorder::new(1, [255; 3], [0; 3]);
+        let mut screen = [[0u8; 64]; 32];
+
+        recorder.push(&screen);
+        recorder.push(&screen);
+        screen[0][0] = 1;
+        recorder.push(&screen);
+
+        assert_eq!(recorder.frames.len(), 2);
+        assert_eq!(recorder.frame_count(), 3);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{DEFAULT_CYCLES_PER_FRAME, palette::Palette, postprocess::Filter};
+
+// Settings shared by every frontend, kept in a TOML file in the user's config directory
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Config {
+    // Palette used when a ROM has none of its own
+    pub palette: String,
+    // User defined palettes, on top of the presets
+    pub palettes: Vec<Palette>,
+    // Last palette picked for each ROM, by file name
+    pub rom_palettes: HashMap<String, String>,
+    // Last filter picked for each ROM, by file name
+    pub rom_filters: HashMap<String, Filter>,
+    pub debugger: DebuggerSettings
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            palette: Palette::default().name,
+            palettes: vec![],
+            rom_palettes: HashMap::new(),
+            rom_filters: HashMap::new(),
+            debugger: DebuggerSettings::default()
+        }
+    }
+}
+
+pub const MAX_RECENT_ROMS: usize = 10;
+
+// State of the debugger, saved on exit and restored on the next start
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct DebuggerSettings {
+    pub cycles_per_frame: u32,
+    // Most recent first
+    pub recent_roms: Vec<String>,
+    pub window_size: [f32; 2],
+    pub window_position: Option<[f32; 2]>,
+    pub debug: bool,
+    pub show_memory_window: bool,
+    pub memory_start: u16,
+    pub memory_end: u16,
+    pub continuous: bool,
+    pub hot_reload: bool,
+    // Name of the keyboard key for each key of the keypad, from 0 to F
+    pub keymap: Vec<String>
+}
+
+impl Default for DebuggerSettings {
+    fn default() -> Self {
+        DebuggerSettings {
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME as u32,
+            recent_roms: vec![],
+            window_size: [900.0, 600.0],
+            window_position: None,
+            debug: false,
+            show_memory_window: false,
+            memory_start: 0x0200,
+            memory_end: 0x020F,
+            continuous: false,
+            hot_reload: false,
+            keymap: ["1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V"]
+                .map(String::from)
+                .to_vec()
+        }
+    }
+}
+
+impl DebuggerSettings {
+
+    pub fn add_recent_rom(&mut self, path: &str) {
+        self.recent_roms.retain(|recent| recent != path);
+        self.recent_roms.insert(0, path.to_owned());
+        self.recent_roms.truncate(MAX_RECENT_ROMS);
+    }
+}
+
+impl Config {
+
+    pub fn dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8-emulator"))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // A missing or unreadable file just gives the defaults, so a broken config never stops the emulator
+    pub fn load() -> Config {
+        let Some(path) = Config::path() else { return Config::default() };
+        if !path.exists() {
+            return Config::default();
+        }
+        Config::load_from(&path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Config::default()
+        })
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Config::path().ok_or("No config directory for this user".to_owned())?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    // Presets first; a user palette with the same name replaces the preset
+    pub fn all_palettes(&self) -> Vec<Palette> {
+        let mut palettes: Vec<Palette> = Palette::presets()
+            .into_iter()
+            .filter(|preset| Palette::find(&self.palettes, &preset.name).is_none())
+            .collect();
+        palettes.extend(self.palettes.iter().cloned());
+        palettes
+    }
+
+    // The palette last picked for the ROM, then the suggested one (e.g. from the ROM database),
+    // then the last one used at all
+    pub fn palette_for(&self, rom: Option<&Path>, suggested: Option<&str>) -> Palette {
+        let palettes = self.all_palettes();
+        rom.and_then(rom_key)
+            .and_then(|key| self.rom_palettes.get(&key))
+            .and_then(|name| Palette::find(&palettes, name))
+            .or_else(|| suggested.and_then(|name| Palette::find(&palettes, name)))
+            .or_else(|| Palette::find(&palettes, &self.palette))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn remember_palette(&mut self, rom: Option<&Path>, name: &str) {
+        self.palette = name.to_owned();
+        if let Some(key) = rom.and_then(rom_key) {
+            self.rom_palettes.insert(key, name.to_owned());
+        }
+    }
+
+    // No filter until one is picked for the ROM
+    pub fn filter_for(&self, rom: &Path) -> Filter {
+        rom_key(rom)
+            .and_then(|key| self.rom_filters.get(&key))
+            .copied()
+            .unwrap_or(Filter::None)
+    }
+
+    pub fn remember_filter(&mut self, rom: &Path, filter: Filter) {
+        if let Some(key) = rom_key(rom) {
+            self.rom_filters.insert(key, filter);
+        }
+    }
+
+    pub fn add_palette(&mut self, palette: Palette) {
+        self.palettes.retain(|other| !other.name.eq_ignore_ascii_case(&palette.name));
+        self.palettes.push(palette);
+    }
+}
+
+fn rom_key(rom: &Path) -> Option<String> {
+    rom.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod config_test {
+    use std::path::Path;
+    use super::{Config, DebuggerSettings, Filter, Palette, MAX_RECENT_ROMS};
+
+    #[test]
+    fn toml_roundtrip() {
+        let mut config = Config::default();
+        config.add_palette(Palette::new("mine", [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]]));
+        config.remember_palette(Some(Path::new("roms/pong.ch8")), "amber");
+        config.remember_filter(Path::new("roms/pong.ch8"), Filter::Phosphor(0.5));
+        config.remember_filter(Path::new("roms/tetris.ch8"), Filter::Blend);
+        config.debugger.window_position = Some([10.0, 20.0]);
+        config.debugger.add_recent_rom("roms/pong.ch8");
+
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&text).unwrap(), config);
+        // Missing keys fall back to the defaults
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        let partial = toml::from_str::<Config>("[debugger]\ncycles_per_frame = 15\n").unwrap();
+        assert_eq!(partial.debugger.cycles_per_frame, 15);
+        assert_eq!(partial.debugger.memory_end, 0x020F);
+    }
+
+    #[test]
+    fn recent_roms() {
+        let mut settings = DebuggerSettings::default();
+        for i in 0..MAX_RECENT_ROMS + 2 {
+            settings.add_recent_rom(&format!("{}.ch8", i));
+        }
+        settings.add_recent_rom("5.ch8");
+
+        assert_eq!(settings.recent_roms.len(), MAX_RECENT_ROMS);
+        assert_eq!(settings.recent_roms[0], "5.ch8");
+        assert_eq!(settings.recent_roms[1], "11.ch8");
+        assert_eq!(settings.recent_roms.iter().filter(|rom| *rom == "5.ch8").count(), 1);
+    }
+
+    #[test]
+    fn palette_per_rom() {
+        let mut config = Config::default();
+        config.remember_palette(Some(Path::new("/a/pong.ch8")), "amber");
+        config.remember_palette(Some(Path::new("/a/tetris.ch8")), "lcd");
+
+        // Keyed by file name, so the ROM can move
+        assert_eq!(config.palette_for(Some(Path::new("/b/pong.ch8")), None).name, "amber");
+        assert_eq!(config.palette_for(Some(Path::new("/b/other.ch8")), None).name, "lcd");
+        assert_eq!(config.palette_for(None, None).name, "lcd");
+
+        // A suggestion only counts for ROMs without a palette of their own
+        assert_eq!(config.palette_for(Some(Path::new("/b/pong.ch8")), Some("xo-chip")).name, "amber");
+        assert_eq!(config.palette_for(Some(Path::new("/b/other.ch8")), Some("xo-chip")).name, "xo-chip");
+
+        config.rom_palettes.insert("gone.ch8".to_owned(), "deleted".to_owned());
+        assert_eq!(config.palette_for(Some(Path::new("gone.ch8")), None).name, "lcd");
+    }
+
+    #[test]
+    fn filter_per_rom() {
+        let mut config = Config::default();
+        config.remember_filter(Path::new("/a/pong.ch8"), Filter::Phosphor(0.45));
+        config.remember_filter(Path::new("/a/tetris.ch8"), Filter::Blend);
+
+        // Keyed by file name like the palettes, the persistence kept with it
+        assert_eq!(config.filter_for(Path::new("/b/pong.ch8")), Filter::Phosphor(0.45));
+        assert_eq!(config.filter_for(Path::new("/b/tetris.ch8")), Filter::Blend);
+        assert_eq!(config.filter_for(Path::new("/b/other.ch8")), Filter::None);
+
+        let text = toml::to_string(&config).unwrap();
+        assert!(text.contains("\"tetris.ch8\" = \"blend\""), "{}", text);
+    }
+
+    #[test]
+    fn user_palette_replaces_preset() {
+        let mut config = Config::default();
+        config.add_palette(Palette::new("Amber", [[0; 3]; 4]));
+
+        let palettes = config.all_palettes();
+        assert_eq!(palettes.iter().filter(|p| p.name.eq_ignore_ascii_case("amber")).count(), 1);
+        assert_eq!(Palette::find(&palettes, "amber").unwrap().on(), [0; 3]);
+    }
+}
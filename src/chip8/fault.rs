@@ -0,0 +1,78 @@
+use std::fmt;
+
+use super::stack::StackError;
+
+// Why the program stopped, nothing runs after that until a reset
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fault {
+    Stack(StackError),
+    // An instruction this interpreter doesn't run
+    UnknownOpcode(u16),
+    // A read or write going past the end of memory, from this address
+    OutOfMemory(u16)
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::Stack(e) => write!(f, "{}", e),
+            Fault::UnknownOpcode(opcode) => write!(f, "Unknown opcode {:#06x}", opcode),
+            Fault::OutOfMemory(address) => write!(f, "Memory access past the end of memory, from {:#06x}", address)
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+impl From<StackError> for Fault {
+    fn from(e: StackError) -> Self {
+        Fault::Stack(e)
+    }
+}
+
+#[cfg(test)]
+mod fault_test {
+    use super::super::Chip8;
+    use super::Fault;
+
+    fn run(rom: &[u8], cycles: usize) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.insert_rom(rom.to_vec()).unwrap();
+        chip.load();
+        for _ in 0..cycles {
+            chip.cycle();
+        }
+        chip
+    }
+
+    #[test]
+    fn unknown_opcode_stops_on_it() {
+        // LD V0, 0x01; 8XYF
+        let chip = run(&[0x60, 0x01, 0x80, 0x0F], 2);
+        assert_eq!(chip.fault, Some(Fault::UnknownOpcode(0x800F)));
+        assert_eq!(chip.cpu.read_pc(), 0x0202);
+
+        // SYS 0x0123, machine code on the original hardware
+        let chip = run(&[0x01, 0x23], 1);
+        assert_eq!(chip.fault, Some(Fault::UnknownOpcode(0x0123)));
+        assert_eq!(Chip8::get_mnemonic(0x800F), "DW 0x800f");
+    }
+
+    #[test]
+    fn memory_access_past_the_end() {
+        // LD I, 0x0FFE; LD V2, I
+        let chip = run(&[0xAF, 0xFE, 0xF2, 0x65], 2);
+        assert_eq!(chip.fault, Some(Fault::OutOfMemory(0x0FFE)));
+        assert_eq!(chip.cpu.read_pc(), 0x0202);
+
+        // LD I, 0x0FFE; LD V1, I reads the last two bytes
+        let chip = run(&[0xAF, 0xFE, 0xF1, 0x65], 2);
+        assert_eq!(chip.fault, None);
+
+        // JP 0x0FFF, where only half an instruction fits
+        let mut chip = run(&[0x1F, 0xFF], 2);
+        assert_eq!(chip.fault, Some(Fault::OutOfMemory(0x0FFF)));
+        // Nothing runs any more, but frames still end
+        chip.step_frame();
+    }
+}
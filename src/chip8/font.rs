@@ -0,0 +1,265 @@
+use std::path::Path;
+
+use super::{Chip8, stack::MEMORY_STACK_BASE};
+
+pub const SMALL_GLYPH_SIZE: usize = 5;
+pub const BIG_GLYPH_SIZE: usize = 10;
+pub const SMALL_FONT_SIZE: usize = 16 * SMALL_GLYPH_SIZE;
+pub const BIG_FONT_SIZE: usize = 16 * BIG_GLYPH_SIZE;
+// The big font is loaded right after the small one
+pub const FONT_SIZE: usize = SMALL_FONT_SIZE + BIG_FONT_SIZE;
+pub const DEFAULT_FONT_BASE: u16 = 0x000;
+// Keeps the font clear of a memory-mapped stack
+pub const MAX_FONT_BASE: u16 = MEMORY_STACK_BASE - FONT_SIZE as u16;
+
+// The COSMAC VIP interpreter
+pub const VIP_FONT: [[u8; SMALL_GLYPH_SIZE]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x60, 0x20, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0xA0, 0xA0, 0xF0, 0x20, 0x20], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x10, 0x10, 0x10], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xF0, 0x50, 0x70, 0x50, 0xF0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xF0, 0x50, 0x50, 0x50, 0xF0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+// The DREAM 6800's CHIPOS, three pixels wide
+pub const DREAM_6800_FONT: [[u8; SMALL_GLYPH_SIZE]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0], // 0
+    [0x40, 0x40, 0x40, 0x40, 0x40], // 1
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0], // 2
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0], // 3
+    [0x80, 0xA0, 0xA0, 0xE0, 0x20], // 4
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0], // 5
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0], // 6
+    [0xE0, 0x20, 0x20, 0x20, 0x20], // 7
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0], // 8
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0], // 9
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0], // A
+    [0xC0, 0xA0, 0xE0, 0xA0, 0xC0], // B
+    [0xE0, 0x80, 0x80, 0x80, 0xE0], // C
+    [0xC0, 0xA0, 0xA0, 0xA0, 0xC0], // D
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0], // E
+    [0xE0, 0x80, 0xC0, 0x80, 0x80], // F
+];
+
+// The ETI-660, also three pixels wide, with lowercase b and d
+pub const ETI_660_FONT: [[u8; SMALL_GLYPH_SIZE]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0], // 0
+    [0x20, 0x20, 0x20, 0x20, 0x20], // 1
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0], // 2
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0], // 3
+    [0xA0, 0xA0, 0xE0, 0x20, 0x20], // 4
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0], // 5
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0], // 6
+    [0xE0, 0x20, 0x20, 0x20, 0x20], // 7
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0], // 8
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0], // 9
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0], // A
+    [0x80, 0x80, 0xE0, 0xA0, 0xE0], // B
+    [0xE0, 0x80, 0x80, 0x80, 0xE0], // C
+    [0x20, 0x20, 0xE0, 0xA0, 0xE0], // D
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0], // E
+    [0xE0, 0x80, 0xC0, 0x80, 0x80], // F
+];
+
+// SUPER-CHIP 1.1, the font most emulators use
+pub const SCHIP_FONT: [[u8; SMALL_GLYPH_SIZE]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x20, 0x60, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0x90, 0x90, 0xF0, 0x10, 0x10], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x20, 0x40, 0x40], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xE0, 0x90, 0x90, 0x90, 0xE0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+// SUPER-CHIP 1.1 only has big digits, A to F are left blank
+pub const SCHIP_BIG_FONT: [[u8; BIG_GLYPH_SIZE]; 16] = [
+    [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C], // 0
+    [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C], // 1
+    [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF], // 2
+    [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C], // 3
+    [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06], // 4
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C], // 5
+    [0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C], // 6
+    [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60], // 7
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C], // 8
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C], // 9
+    [0; BIG_GLYPH_SIZE], // A
+    [0; BIG_GLYPH_SIZE], // B
+    [0; BIG_GLYPH_SIZE], // C
+    [0; BIG_GLYPH_SIZE], // D
+    [0; BIG_GLYPH_SIZE], // E
+    [0; BIG_GLYPH_SIZE], // F
+];
+
+// The glyphs LD F, Vx and LD HF, Vx point to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Font {
+    pub small: [[u8; SMALL_GLYPH_SIZE]; 16],
+    pub big: [[u8; BIG_GLYPH_SIZE]; 16]
+}
+
+impl Font {
+
+    // 80 bytes for the small font only, or 240 with the big font after it
+    pub fn from_bytes(data: &[u8]) -> Result<Font, String> {
+        if data.len() != SMALL_FONT_SIZE && data.len() != FONT_SIZE {
+            return Err(format!("A font has {} or {} bytes, not {}", SMALL_FONT_SIZE, FONT_SIZE, data.len()));
+        }
+        let mut font = FontSet::Schip.font();
+        for (glyph, bytes) in font.small.iter_mut().zip(data.chunks(SMALL_GLYPH_SIZE)) {
+            glyph.copy_from_slice(bytes);
+        }
+        for (glyph, bytes) in font.big.iter_mut().zip(data[SMALL_FONT_SIZE..].chunks(BIG_GLYPH_SIZE)) {
+            glyph.copy_from_slice(bytes);
+        }
+        Ok(font)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Font, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Font::from_bytes(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        FontSet::Schip.font()
+    }
+}
+
+// The built-in small fonts, all with the SUPER-CHIP big font as it's the only one there is
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FontSet {
+    Vip,
+    Dream6800,
+    Eti660,
+    Schip
+}
+
+impl FontSet {
+
+    pub const ALL: [FontSet; 4] = [FontSet::Vip, FontSet::Dream6800, FontSet::Eti660, FontSet::Schip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontSet::Vip => "vip",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::Eti660 => "eti660",
+            FontSet::Schip => "schip"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FontSet> {
+        FontSet::ALL.into_iter().find(|set| set.name() == name)
+    }
+
+    pub fn font(&self) -> Font {
+        let small = match self {
+            FontSet::Vip => VIP_FONT,
+            FontSet::Dream6800 => DREAM_6800_FONT,
+            FontSet::Eti660 => ETI_660_FONT,
+            FontSet::Schip => SCHIP_FONT
+        };
+        Font { small, big: SCHIP_BIG_FONT }
+    }
+}
+
+impl Chip8 {
+
+    // Takes effect right away, and is kept across resets like the quirks
+    pub fn set_font(&mut self, font: Font, base: u16) -> Result<(), String> {
+        if base > MAX_FONT_BASE {
+            return Err(format!("The font can't start after {:#05x}", MAX_FONT_BASE));
+        }
+        // Moving the font doesn't leave the old glyphs behind
+        for addr in self.font_base..self.font_base + FONT_SIZE as u16 {
+            self.memory.write_u8(addr, 0);
+        }
+        self.font = font;
+        self.font_base = base;
+        self.load_font();
+        Ok(())
+    }
+
+    pub fn font_base(&self) -> u16 {
+        self.font_base
+    }
+
+    pub(crate) fn load_font(&mut self) {
+        let glyphs = self.font.small.iter().flatten().chain(self.font.big.iter().flatten());
+        for (addr, byte) in (self.font_base..).zip(glyphs) {
+            self.memory.write_u8(addr, *byte);
+        }
+    }
+
+    pub(crate) fn small_glyph(&self, digit: u8) -> u16 {
+        self.font_base + (digit & 0xF) as u16 * SMALL_GLYPH_SIZE as u16
+    }
+
+    pub(crate) fn big_glyph(&self, digit: u8) -> u16 {
+        self.font_base + SMALL_FONT_SIZE as u16 + (digit & 0xF) as u16 * BIG_GLYPH_SIZE as u16
+    }
+}
+
+#[cfg(test)]
+mod font_test {
+    use super::super::Chip8;
+    use super::{Font, FontSet, DEFAULT_FONT_BASE, MAX_FONT_BASE, SMALL_FONT_SIZE, VIP_FONT};
+
+    // LD V0, 0x0B; LD F, V0; LD HF, V0
+    const FONT_LOOKUP: [u8; 6] = [0x60, 0x0B, 0xF0, 0x29, 0xF0, 0x30];
+
+    #[test]
+    fn glyphs_follow_the_base() {
+        let mut chip = Chip8::new();
+        chip.set_font(FontSet::Vip.font(), 0x50).unwrap();
+        chip.insert_rom(FONT_LOOKUP.to_vec()).unwrap();
+        chip.reset();
+
+        chip.cycle();
+        chip.cycle();
+        assert_eq!(chip.cpu.i_register, 0x50 + 11 * 5);
+        assert_eq!(chip.memory.read_u8(chip.cpu.i_register + 1), VIP_FONT[0xB][1]);
+        chip.cycle();
+        assert_eq!(chip.cpu.i_register, 0x50 + SMALL_FONT_SIZE as u16 + 11 * 10);
+    }
+
+    #[test]
+    fn base_must_leave_room() {
+        let mut chip = Chip8::new();
+        assert!(chip.set_font(Font::default(), MAX_FONT_BASE + 1).is_err());
+        assert_eq!(chip.font_base(), DEFAULT_FONT_BASE);
+        assert!(chip.set_font(Font::default(), MAX_FONT_BASE).is_ok());
+    }
+
+    #[test]
+    fn font_from_bytes() {
+        let small: Vec<u8> = (0..SMALL_FONT_SIZE as u8).collect();
+        let font = Font::from_bytes(&small).unwrap();
+        assert_eq!(font.small[1], [5, 6, 7, 8, 9]);
+        // Without big glyphs the SUPER-CHIP ones are kept
+        assert_eq!(font.big, FontSet::Schip.font().big);
+        assert!(Font::from_bytes(&small[1..]).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use super::{Chip8, timing::Speed};
+
+pub trait VideoSink {
+    fn present(&mut self, chip: &Chip8);
+}
+
+pub trait AudioSink {
+    fn set_tone(&mut self, on: bool);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputEvent {
+    KeyDown(u8),
+    KeyUp(u8),
+    SetSpeed(Speed),
+    Quit
+}
+
+pub trait InputSource {
+    // Called until it returns None, once before every instruction
+    fn poll(&mut self) -> Option<InputEvent>;
+
+    // Called after the events, before every instruction, by frontends driving the emulator
+    // further than its input, like the debugger stopping and stepping it. Blocking holds it
+    fn control(&mut self, _chip: &mut Chip8) {}
+}
+
+// The unit type works as a do-nothing frontend for headless runs
+impl VideoSink for () {
+    fn present(&mut self, _chip: &Chip8) {}
+}
+
+impl AudioSink for () {
+    fn set_tone(&mut self, _on: bool) {}
+}
+
+impl InputSource for () {
+    fn poll(&mut self) -> Option<InputEvent> { None }
+}
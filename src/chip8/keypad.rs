@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 pub const DEFAULT_LAYOUT: [u8; 16] = [
     0x01, 0x02, 0x03, 0x0C,
     0x04, 0x05, 0x06, 0x0D,
@@ -5,21 +7,46 @@ pub const DEFAULT_LAYOUT: [u8; 16] = [
     0x0A, 0x00, 0x0B, 0x0F
 ];
 
+// Events older than this many frames are dropped from the queue
+pub const EVENT_HISTORY_FRAMES: u64 = 60;
+pub const MAX_EVENTS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub down: bool,
+    // Frame it happened in, counted from the start
+    pub frame: u64
+}
+
 pub struct Keypad {
     
-    pub keys: [u8; 16]
+    pub keys: [u8; 16],
+    // One bit per key, set when it goes down or up, until clear_edges
+    pub presses: u16,
+    pub releases: u16,
+    // One bit per key pressed since the last SKP or SKNP on it, so that
+    // a press and release within the same frame is still seen once
+    pub unseen_presses: u16,
+    pub frame: u64,
+    events: VecDeque<KeyEvent>
 }
 
 impl Keypad {
 
     pub fn new() -> Self {
         Keypad { 
-            keys: DEFAULT_LAYOUT
+            keys: DEFAULT_LAYOUT,
+            presses: 0,
+            releases: 0,
+            unseen_presses: 0,
+            frame: 0,
+            events: VecDeque::new()
         }
     }
 
     pub fn is_down(&self, key: u8) -> bool {
-        self.keys[(key & 0xF) as usize] & 0xF0 != 0
+        is_held(&self.keys, key)
     }
 
     pub fn is_up(&self, key: u8) -> bool {
@@ -27,10 +54,190 @@ impl Keypad {
     }
 
     pub fn set_key(&mut self, key: u8, down: bool) {
+        let key = key & 0xF;
+        if down && self.is_up(key) {
+            self.presses |= 1 << key;
+            self.unseen_presses |= 1 << key;
+            self.push_event(key, true);
+        } else if !down && self.is_down(key) {
+            self.releases |= 1 << key;
+            self.push_event(key, false);
+        }
+
         if down {
-            self.keys[(key & 0xF) as usize] |= 0x10;
+            self.keys[key as usize] |= 0x10;
         } else {
-            self.keys[(key & 0xF) as usize] &= 0x0F;
+            self.keys[key as usize] &= 0x0F;
+        }
+    }
+
+    fn push_event(&mut self, key: u8, down: bool) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(KeyEvent { key, down, frame: self.frame });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        while let Some(event) = self.events.front() && event.frame + EVENT_HISTORY_FRAMES <= self.frame {
+            self.events.pop_front();
+        }
+    }
+
+    // Oldest first
+    pub fn events(&self) -> impl Iterator<Item = &KeyEvent> {
+        self.events.iter()
+    }
+
+    // Whether the key went down during the current frame
+    pub fn just_pressed(&self, key: u8) -> bool {
+        self.events.iter().any(|event| event.key == key & 0xF && event.down && event.frame == self.frame)
+    }
+
+    // Whether the key went up during the current frame
+    pub fn just_released(&self, key: u8) -> bool {
+        self.events.iter().any(|event| event.key == key & 0xF && !event.down && event.frame == self.frame)
+    }
+
+    // What SKP and SKNP see: a key counts as down while held, or if it was let go
+    // before any of them checked it
+    pub fn check(&mut self, key: u8) -> bool {
+        let key = key & 0xF;
+        let unseen = self.unseen_presses & (1 << key) != 0;
+        self.unseen_presses &= !(1 << key);
+        self.is_down(key) || unseen
+    }
+
+    pub fn clear_edges(&mut self) {
+        self.presses = 0;
+        self.releases = 0;
+    }
+
+    // Lowest key pressed since the edges were cleared
+    pub fn first_pressed(&self) -> Option<u8> {
+        lowest_key(self.presses)
+    }
+
+    // Lowest key both pressed and released since the edges were cleared
+    pub fn first_released(&self) -> Option<u8> {
+        lowest_key(self.presses & self.releases)
+    }
+}
+
+// The key at a position of the 4x4 keypad, counted row by row
+pub fn key_at(cell: usize) -> u8 {
+    DEFAULT_LAYOUT[cell]
+}
+
+// Whether a key is down in a copy of the keys, such as the one the debugger gets
+pub fn is_held(keys: &[u8; 16], key: u8) -> bool {
+    keys[(key & 0xF) as usize] & 0xF0 != 0
+}
+
+fn lowest_key(keys: u16) -> Option<u8> {
+    if keys == 0 { None } else { Some(keys.trailing_zeros() as u8) }
+}
+
+#[cfg(test)]
+mod keypad_test {
+    use super::{Keypad, KeyEvent, EVENT_HISTORY_FRAMES, key_at, is_held};
+
+    #[test]
+    fn edges_are_only_recorded_on_changes() {
+        let mut keypad = Keypad::new();
+        keypad.set_key(5, false);
+        assert_eq!(keypad.releases, 0);
+
+        keypad.set_key(5, true);
+        keypad.set_key(5, true);
+        keypad.set_key(0xA, true);
+        assert_eq!(keypad.presses, 1 << 5 | 1 << 0xA);
+        assert_eq!(keypad.first_pressed(), Some(5));
+        assert_eq!(keypad.first_released(), None);
+
+        keypad.set_key(0xA, false);
+        assert_eq!(keypad.first_released(), Some(0xA));
+
+        keypad.clear_edges();
+        assert_eq!(keypad.first_pressed(), None);
+        assert!(keypad.is_down(5));
+    }
+
+    #[test]
+    fn release_needs_a_press_first() {
+        let mut keypad = Keypad::new();
+        keypad.set_key(3, true);
+        keypad.clear_edges();
+
+        // Held from before, so letting it go is not a full press
+        keypad.set_key(3, false);
+        assert_eq!(keypad.first_released(), None);
+        keypad.set_key(3, true);
+        keypad.set_key(3, false);
+        assert_eq!(keypad.first_released(), Some(3));
+    }
+
+    #[test]
+    fn events_carry_their_frame() {
+        let mut keypad = Keypad::new();
+        keypad.set_key(1, true);
+        keypad.end_frame();
+        keypad.set_key(1, false);
+
+        assert_eq!(keypad.events().copied().collect::<Vec<_>>(), vec![
+            KeyEvent { key: 1, down: true, frame: 0 },
+            KeyEvent { key: 1, down: false, frame: 1 }
+        ]);
+        assert!(!keypad.just_pressed(1));
+        assert!(keypad.just_released(1));
+
+        for _ in 0..EVENT_HISTORY_FRAMES {
+            keypad.end_frame();
+        }
+        assert_eq!(keypad.events().count(), 0);
+    }
+
+    #[test]
+    fn pressing_a_cell_holds_its_key() {
+        for cell in 0..16 {
+            let mut keypad = Keypad::new();
+            keypad.set_key(key_at(cell), true);
+
+            for key in 0..16 {
+                assert_eq!(is_held(&keypad.keys, key), key == key_at(cell));
+            }
         }
+        // Row by row, as on the COSMAC VIP
+        assert_eq!(key_at(0), 0x1);
+        assert_eq!(key_at(13), 0x0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn short_press_is_seen_once() {
+        let mut keypad = Keypad::new();
+        keypad.set_key(8, true);
+        keypad.set_key(8, false);
+
+        assert!(keypad.check(8));
+        assert!(!keypad.check(8));
+        // Other keys don't use it up
+        keypad.set_key(2, true);
+        keypad.set_key(2, false);
+        assert!(!keypad.check(3));
+        assert!(keypad.check(2));
+    }
+
+    #[test]
+    fn skp_sees_a_press_shorter_than_a_frame() {
+        // SKP V0; JP 0x0200; ADD V1, 1; JP 0x0206
+        let mut chip = super::super::Chip8::new();
+        chip.insert_rom(vec![0xE0, 0x9E, 0x12, 0x00, 0x71, 0x01, 0x12, 0x06]).unwrap();
+        chip.load();
+
+        chip.key_down(0);
+        chip.key_up(0);
+        chip.step_frame();
+        assert_eq!(chip.cpu.v_registers[1], 1);
+    }
+}
@@ -10,6 +10,11 @@ impl Memory {
         }
     }
 
+    // Whether the `len` bytes from `addr` are all in the address space
+    pub fn contains(&self, addr: u16, len: usize) -> bool {
+        addr as usize + len <= self.address_space.len()
+    }
+
     pub fn write_u8(&mut self, addr: u16, data: u8) {
         match addr {
             0x0000..=0x0FFF => { self.address_space[addr as usize] = data; }
@@ -1,9 +1,38 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::time;
 
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 9;
+
 pub mod cpu;
 pub mod memory;
 pub mod display;
 pub mod keypad;
+pub mod frontend;
+pub mod beeper;
+pub mod savestate;
+pub mod postprocess;
+pub mod quirks;
+pub mod timing;
+pub mod callstack;
+pub mod stack;
+pub mod fault;
+pub mod font;
+pub mod symbols;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod avrecord;
+pub mod movie;
+pub mod palette;
+pub mod viewport;
+pub mod rom;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "romdb")]
+pub mod romdb;
+
+use frontend::InputEvent;
+#[cfg(not(target_arch = "wasm32"))]
+use frontend::{VideoSink, AudioSink, InputSource};
 
 pub struct Chip8 {
     pub cpu: cpu::CPU,
@@ -15,11 +44,24 @@ pub struct Chip8 {
     resume_from: u16,
 
     pub waiting_for_key: bool,
-    last_poll: std::time::Instant,
+    pub waiting_for_vblank: bool,
+    rng_state: u32,
 
     rom: Vec<u8>,
     pub new_draw: bool,
-
+    pub vblank: bool,
+    pub quirks: quirks::Quirks,
+    pub timing: timing::Timing,
+    pub stack: stack::Stack,
+    font: font::Font,
+    font_base: u16,
+    // Set when the program did something it can't go on from, nothing runs after that
+    pub fault: Option<fault::Fault>,
+    // Instructions per 60 Hz frame with fixed timing
+    pub cycles_per_frame: usize,
+    pub speed: timing::Speed,
+
+    // Instructions, or VIP machine cycles, since the frame started
     cycles: usize,
     pub exit: bool
 }
@@ -27,26 +69,12 @@ pub struct Chip8 {
 impl Chip8 {
 
     pub fn new() -> Self { 
-        // Load sprites in memory
-        let mut mem = memory::Memory::new();
-        let ref sprites = display::DEFAULT_SPRITES;
-
-        let mut base_addr = 0x0000;
-        for i in 0..sprites.len() as u16 {
-
-            for j in 0..sprites[i as usize].len() as u16 {
-                mem.write_u8(base_addr + i + j, sprites[i as usize][j as usize]);
-            }
-            base_addr += 4;
-        }
-        
         let mut cpu= cpu::CPU::new();
-        cpu.set_sp(0x4e);
         cpu.set_pc(0x0200);
 
-        Chip8 {
+        let mut chip = Chip8 {
             cpu: cpu,
-            memory: mem,
+            memory: memory::Memory::new(),
             display: display::Display::new(),
             keypad: keypad::Keypad::new(),
 
@@ -54,13 +82,25 @@ impl Chip8 {
             resume_from: 0,
 
             waiting_for_key: false,
+            waiting_for_vblank: false,
 
-            last_poll: time::Instant::now(),
+            rng_state: 0x2545_F491,
             rom: vec![],
             new_draw: false,
+            vblank: false,
+            quirks: quirks::Quirks::new(),
+            timing: timing::Timing::Fixed,
+            stack: stack::Stack::new(),
+            font: font::Font::default(),
+            font_base: font::DEFAULT_FONT_BASE,
+            fault: None,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            speed: timing::Speed::Normal,
             cycles: 0,
             exit: false
-        }
+        };
+        chip.load_font();
+        chip
     }
 
     pub fn reset(&mut self) {
@@ -69,31 +109,24 @@ impl Chip8 {
         self.opcode = 0;
         self.resume_from = 0;
         self.waiting_for_key = false;
-        self.last_poll = time::Instant::now();
-
-        let mut mem = memory::Memory::new();
-        let ref sprites = display::DEFAULT_SPRITES;
+        self.waiting_for_vblank = false;
+        self.stack.clear();
+        self.fault = None;
 
-        let mut base_addr = 0x0000;
-        for i in 0..sprites.len() as u16 {
-
-            for j in 0..sprites[i as usize].len() as u16 {
-                mem.write_u8(base_addr + i + j, sprites[i as usize][j as usize]);
-            }
-            base_addr += 4;
-        }
-        
         let mut cpu= cpu::CPU::new();
-        cpu.set_sp(0x4e);
         cpu.set_pc(0x0200);
 
         self.cpu = cpu;
-        self.memory = mem;
+        self.memory = memory::Memory::new();
+        self.load_font();
         self.load();
     }
 
-    pub fn insert_rom(&mut self, program: Vec<u8>) {
+    // The program is only kept if it fits in memory after 0x200
+    pub fn insert_rom(&mut self, program: Vec<u8>) -> Result<(), rom::RomError> {
+        rom::check_size(&program, rom::Platform::Chip8)?;
         self.rom = program;
+        Ok(())
     }
 
     pub fn load(&mut self) {
@@ -107,16 +140,27 @@ impl Chip8 {
         self.cpu.set_pc(self.resume_from);
     }
 
-    pub fn fetch(&mut self) {
-        if self.waiting_for_key { return }
-        self.opcode = self.memory.read_u16(self.cpu.read_pc());
+    pub fn key_down(&mut self, key: u8) {
+        self.keypad.set_key(key, true);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keypad.set_key(key, false);
+    }
 
-        #[cfg(feature = "debug-ver")]
-        println!("Ho fetchato 0x{:x} all'indirizzo 0x{:x}", self.opcode, self.cpu.program_counter);
+    pub fn fetch(&mut self) {
+        if self.waiting_for_key || self.waiting_for_vblank || self.fault.is_some() { return }
+        let pc = self.cpu.read_pc();
+        // The last byte of memory only holds half an instruction
+        if !self.memory.contains(pc, 2) {
+            self.fault = Some(fault::Fault::OutOfMemory(pc));
+            return;
+        }
+        self.opcode = self.memory.read_u16(pc);
     }
 
     pub fn decode_execute(&mut self) {
-        if self.waiting_for_key { return }
+        if self.waiting_for_key || self.waiting_for_vblank || self.fault.is_some() { return }
 
         self.cpu.increment_pc();
         self.resume_from = self.cpu.read_pc();
@@ -156,7 +200,7 @@ impl Chip8 {
                     0x6 => self.shr_vx(),
                     0x7 => self.subn_vx_vy(),
                     0xE => self.shl_vx(),
-                    _ => panic!("Impossible opcode {:2x}", self.opcode)
+                    _ => self.unknown_opcode()
                 }
             }
 
@@ -174,7 +218,7 @@ impl Chip8 {
                 match self.opcode & 0x00FF {
                     0x9E => self.skp_vx(),
                     0xA1 => self.sknp_vx(),
-                    _ => panic!("Impossible opcode {:2x}", self.opcode)
+                    _ => self.unknown_opcode()
                 }
             }
 
@@ -186,94 +230,195 @@ impl Chip8 {
                     0x18 => self.ld_st_vx(),
                     0x1E => self.add_i_vx(),
                     0x29 => self.ld_f_vx(),
+                    0x30 => self.ld_hf_vx(),
                     0x33 => self.ld_b_vx(),
                     0x55 => self.ld_i_vx(),
                     0x65 => self.ld_vx_i(),
-                    _ => panic!("Impossible opcode {:2x}", self.opcode)
+                    _ => self.unknown_opcode()
                 }
             }
 
-            _ => { panic!("Impossible opcode {:2x}", self.opcode) }
+            _ => self.unknown_opcode()
         }
     }
 
-    pub fn run(&mut self, framerate_hz: f64) {
-        self.run_with_callbacks(|_| {}, |_| {}, framerate_hz);
+    // Stops the program on the instruction being run, so that it shows where things went wrong
+    fn fail(&mut self, fault: fault::Fault) {
+        self.cpu.set_pc(self.cpu.read_pc().wrapping_sub(2));
+        self.fault = Some(fault);
     }
 
-    pub fn run_with_callback_first<F>(&mut self, callback: F, framerate_hz: f64) 
+    fn unknown_opcode(&mut self) {
+        self.fail(fault::Fault::UnknownOpcode(self.opcode));
+    }
+
+    // Whether the instruction may access `len` bytes from `addr`, otherwise it stops the program
+    fn check_access(&mut self, addr: u16, len: usize) -> bool {
+        if self.memory.contains(addr, len) { return true }
+        self.fail(fault::Fault::OutOfMemory(addr));
+        false
+    }
+
+    // Returns true if the frame ended with this cycle
+    pub fn cycle(&mut self) -> bool {
+        self.fetch();
+        self.execute()
+    }
+
+    // Runs the fetched instruction and counts it towards the frame, whether or not it
+    // actually ran, so that timers keep going while waiting for a key or for vblank.
+    // Returns true if the frame ended with it
+    pub fn execute(&mut self) -> bool {
+        let waiting = self.waiting_for_key || self.waiting_for_vblank || self.fault.is_some();
+        if self.waiting_for_key {
+            self.check_key_wait();
+        }
+
+        match self.timing {
+            timing::Timing::Fixed => {
+                // A wait that just ended still took this cycle, the next instruction comes with the next one
+                if !waiting {
+                    self.decode_execute();
+                }
+                self.cycles += 1;
+            },
+            timing::Timing::Vip => {
+                // While waiting, the interpreter has nothing else to do until the next interrupt
+                if waiting {
+                    self.cycles = timing::VIP_INTERPRETER_CYCLES;
+                } else {
+                    let cost = timing::vip_cycles(self.opcode, &self.cpu);
+                    let pc = self.cpu.read_pc();
+                    self.decode_execute();
+                    let skipped = timing::is_skip(self.opcode) && self.cpu.read_pc() == pc.wrapping_add(4);
+                    self.cycles += cost + if skipped { timing::VIP_SKIP_CYCLES } else { 0 };
+                }
+            }
+        }
+
+        let frame_length = match self.timing {
+            timing::Timing::Fixed => self.cycles_per_frame.max(1),
+            timing::Timing::Vip => timing::VIP_INTERPRETER_CYCLES
+        };
+        if self.cycles < frame_length { return false }
+
+        // An instruction running over the end of a frame eats into the next one
+        self.cycles -= frame_length;
+        self.end_frame();
+        true
+    }
+
+    // Everything that happens at 60 Hz, on the display interrupt
+    fn end_frame(&mut self) {
+        self.tick_timers();
+        self.keypad.end_frame();
+        self.vblank = true;
+        self.waiting_for_vblank = false;
+    }
+
+    // Runs instructions up to and including the next timer tick
+    pub fn step_frame(&mut self) {
+        while !self.cycle() {}
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run(&mut self) {
+        self.run_with_callbacks(|_| {}, |_| {});
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_with_callback_first<F>(&mut self, callback: F) 
         where F: FnMut(&mut Chip8)
     {
-        self.run_with_callbacks(callback, |_| {}, framerate_hz);
+        self.run_with_callbacks(callback, |_| {});
     }
 
-    pub fn run_with_callback_last<L>(&mut self, callback: L, framerate_hz: f64) 
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_with_callback_last<L>(&mut self, callback: L) 
         where L: FnMut(&mut Chip8)
     {
-        self.run_with_callbacks(|_| {}, callback, framerate_hz);
+        self.run_with_callbacks(|_| {}, callback);
     }
 
-    pub fn run_with_callbacks<F, L>(&mut self, mut first: F, mut last: L, framerate_hz: f64)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_with_callbacks<F, L>(&mut self, mut first: F, mut last: L)
         where F: FnMut(&mut Chip8), L: FnMut(&mut Chip8)
     {
-        let mut start = time::Instant::now();
-        let mut end = time::Instant::now();
+        let mut previous = time::Instant::now();
         let mut accumulator = 0.0f64;
-        let threshold = 1.0/framerate_hz;
-        
-        while !self.exit {
 
-            let delta = end - start;
-            start = time::Instant::now();
-            accumulator += delta.as_secs_f64();
+        // Only whole frames are paced, the instructions inside one run back to back
+        let mut frame = |chip: &mut Chip8| loop {
+            first(chip);
+            let frame_ended = chip.cycle();
+            last(chip);
+            if frame_ended || chip.exit { break }
+        };
+        
+        while !self.exit && self.fault.is_none() {
+
+            // All the time since the last round counts, the sleep included
+            let now = time::Instant::now();
+            accumulator = (accumulator + (now - previous).as_secs_f64()).min(timing::MAX_CATCH_UP);
+            previous = now;
+
+            let Some(frame_time) = self.speed.frame_time() else {
+                frame(self);
+                accumulator = 0.0;
+                continue;
+            };
+            while accumulator >= frame_time && !self.exit {
+                frame(self);
+                accumulator -= frame_time;
+            }
 
-            while accumulator >= threshold {
-                first(self);
-                self.fetch();
-                self.decode_execute();
-                self.cycles += 1;
-                if self.cycles == 9 {
-                    self.update_st();
-                    self.update_dt();
-                    self.cycles = 0;
-                }
-                accumulator -= threshold;
-                last(self);
+            if accumulator < frame_time {
+                std::thread::sleep(time::Duration::from_millis(1));
             }
-            end = time::Instant::now();
         }
     }
 
-    pub fn stack_pop(&mut self) -> Option<u16> {
-        match self.cpu.get_sp() {
-            0x4e => None,
-            0x50 | 0x52 | 0x54 | 0x56 | 0x58 | 0x5A | 0x5C | 0x5E | 
-            0x60 | 0x62 | 0x64 | 0x66 | 0x68 | 0x6A | 0x6C | 0x6E => {
-                let result = self.memory.read_u16(self.cpu.get_sp() as u16);
-                #[cfg(feature = "debug-ver")]
-                println!("Ho letto {:x} dallo stacke", result);
-                self.cpu.set_sp(self.cpu.get_sp() - 2);
-                Some(result)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_with_frontend<V, A, I>(&mut self, video: &mut V, audio: &mut A, input: &mut I)
+        where V: VideoSink, A: AudioSink, I: InputSource
+    {
+        let mut beeping = false;
+        self.run_with_callbacks(
+            |chip| {
+                while let Some(event) = input.poll() {
+                    chip.apply_event(event);
+                }
+                input.control(chip);
             },
-            _ => panic!("Incorrect stack pointer 0x{:x}", self.cpu.get_sp())
-        }
+            |chip| {
+                // Frames are presented once per vblank, whether or not something was drawn
+                if chip.vblank {
+                    video.present(chip);
+                    chip.new_draw = false;
+                    chip.vblank = false;
+                }
+
+                let tone = chip.cpu.sound > 0;
+                if tone != beeping {
+                    audio.set_tone(tone);
+                    beeping = tone;
+                }
+            }
+        );
     }
 
-    pub fn stack_push(&mut self, value: u16) {
-        match self.cpu.get_sp() {
-            0x4e | 0x50 | 0x52 | 0x54 | 0x56 | 0x58 | 0x5A | 0x5C |  
-            0x5E | 0x60 | 0x62 | 0x64 | 0x66 | 0x68 | 0x6A | 0x6C => {
-                self.cpu.set_sp(self.cpu.get_sp() + 2);
-                #[cfg(feature = "debug-ver")]
-                println!("Sto per scrivere {:x} nello stacke", value);
-                self.memory.write_u16(self.cpu.get_sp() as u16, value);
-            },
-            _ => panic!("Incorrect stack pointer 0x{:x}", self.cpu.get_sp())
+    pub fn apply_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::KeyDown(key) => self.key_down(key),
+            InputEvent::KeyUp(key) => self.key_up(key),
+            InputEvent::SetSpeed(speed) => self.speed = speed,
+            InputEvent::Quit => self.exit = true
         }
     }
 
+    // Runs machine code on the original hardware, which can't be emulated here
     fn sys_addr(&mut self) {
-        unimplemented!("Modern interpreters ignore this apparently")
+        self.unknown_opcode();
     }
 
     fn cls(&mut self) {
@@ -281,10 +426,10 @@ impl Chip8 {
     }
 
     fn ret(&mut self) {
-        let new_pc = self.stack_pop().expect("Something went wrong");
-        #[cfg(feature = "debug-ver")]
-        println!("STACK POP {:x}", new_pc);
-        self.cpu.set_pc(new_pc);
+        match self.stack_pop() {
+            Ok(new_pc) => self.cpu.set_pc(new_pc),
+            Err(e) => self.fault = Some(e.into())
+        }
     }
 
     fn jp_addr(&mut self) {
@@ -295,11 +440,10 @@ impl Chip8 {
     fn call_addr(&mut self) {
         let addr = self.opcode & 0x0FFF;
         let old_pc = self.cpu.read_pc();
-        #[cfg(feature = "debug-ver")]
-        println!("STACK PUSH {:x}", old_pc);
-        self.stack_push(old_pc);
-
-        self.cpu.set_pc(addr);
+        match self.stack_push(old_pc) {
+            Ok(()) => self.cpu.set_pc(addr),
+            Err(e) => self.fail(e.into())
+        }
     }
 
     fn se_vx_byte(&mut self) {
@@ -473,6 +617,13 @@ impl Chip8 {
     }
 
     fn drw_vx_vy_nibble(&mut self) {
+        // With display wait, DRW only runs as the first instruction of a frame
+        if self.quirks.display_wait && self.cycles != 0 {
+            self.cpu.set_pc(self.cpu.read_pc().wrapping_sub(2));
+            self.waiting_for_vblank = true;
+            return;
+        }
+
         let nibble = (self.opcode & 0x000F) as u16;
         let y = ((self.opcode & 0x00F0) >> 4) as usize;
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
@@ -480,11 +631,14 @@ impl Chip8 {
         let vx = (self.cpu.v_registers[x] as usize) & 63;
         let vy = (self.cpu.v_registers[y] as usize) & 31;
 
+        // Rows below the screen are clipped, and never read
+        let rows = nibble.min(32 - vy as u16);
+        if !self.check_access(self.cpu.i_register, rows as usize) { return }
+
         let mut sprite: u8;
         let mut changed: bool = false;
         self.cpu.v_registers[0xF] = 0;
-        for row in 0..nibble {
-            if (vy + row as usize) == 32 { break }
+        for row in 0..rows {
             sprite = self.memory.read_u8(self.cpu.i_register + row);
             if self.display.draw_sprite(vy + row as usize, vx, sprite) {
                 changed = true;
@@ -498,14 +652,14 @@ impl Chip8 {
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
         let vx = self.cpu.v_registers[x];
 
-        if self.keypad.is_down(vx) { self.cpu.increment_pc() }
+        if self.keypad.check(vx) { self.cpu.increment_pc() }
     }
 
     fn sknp_vx(&mut self) {
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
         let vx = self.cpu.v_registers[x];
 
-        if self.keypad.is_up(vx) { self.cpu.increment_pc() }
+        if !self.keypad.check(vx) { self.cpu.increment_pc() }
     }
 
     fn ld_vx_dt(&mut self) {
@@ -515,13 +669,17 @@ impl Chip8 {
 
     fn ld_vx_k(&mut self) {
         self.waiting_for_key = true;
-        #[cfg(feature = "debug-ver")]
-        println!("Waiting from NOW")
+        // Only keys pressed from now on end the wait
+        self.keypad.clear_edges();
     }
 
-    pub fn resume_ld_vx_k(&mut self, key: u8) {
-        #[cfg(feature = "debug-ver")]
-        println!("RESUMING WITH KEY {:#06x}", key);
+    fn check_key_wait(&mut self) {
+        let key = if self.quirks.wait_for_release {
+            self.keypad.first_released()
+        } else {
+            self.keypad.first_pressed()
+        };
+        let Some(key) = key else { return };
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
         self.cpu.v_registers[x] = key;
         self.resume();
@@ -546,14 +704,21 @@ impl Chip8 {
     fn ld_f_vx(&mut self) {
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
 
-        let addr = self.cpu.v_registers[x] as u16 * 5;
-        self.cpu.i_register = addr;
+        self.cpu.i_register = self.small_glyph(self.cpu.v_registers[x]);
+    }
+
+    fn ld_hf_vx(&mut self) {
+        let x = ((self.opcode & 0x0F00) >> 8) as usize;
+
+        self.cpu.i_register = self.big_glyph(self.cpu.v_registers[x]);
     }
 
     fn ld_b_vx(&mut self) {
         let x = ((self.opcode & 0x0F00) >> 8) as usize;
         let vx = self.cpu.v_registers[x];
 
+        if !self.check_access(self.cpu.i_register, 3) { return }
+
         let units = vx % 10;
         let tens = (vx % 100) - units;
         let hundreds = vx - tens - units;
@@ -564,6 +729,7 @@ impl Chip8 {
 
     fn ld_i_vx(&mut self) {
         let x = (self.opcode & 0x0F00) >> 8;
+        if !self.check_access(self.cpu.i_register, x as usize + 1) { return }
         for j in 0x0u16..=x {
             let vj = self.cpu.v_registers[j as usize];
             self.memory.write_u8(self.cpu.i_register + j, vj);
@@ -572,29 +738,38 @@ impl Chip8 {
 
     fn ld_vx_i(&mut self) {
         let x = (self.opcode & 0x0F00) >> 8;
+        if !self.check_access(self.cpu.i_register, x as usize + 1) { return }
         for j in 0x0u16..=x {
             self.cpu.v_registers[j as usize] = self.memory.read_u8(self.cpu.i_register + j);
         }
     }
 
-    fn random(&mut self, min_incl: u8, max_incl: u8) -> u8 {
-        let min = std::time::Duration::from_secs(min_incl as u64);
-        let max = std::time::Duration::from_secs(max_incl as u64);
-        let r= std::time::Instant::now().duration_since(self.last_poll).clamp(min, max);
-        self.last_poll = std::time::Instant::now();
-
-        (r.as_secs() & 0x0000_0000_0000_FFFF) as u8
+    pub fn seed_rng(&mut self, seed: u32) {
+        // xorshift gets stuck on a zero state
+        self.rng_state = if seed == 0 { 0x2545_F491 } else { seed };
     }
 
-    pub fn update_dt(&mut self) {
-        if self.cpu.delay > 0 { self.cpu.delay -= 1 }
+    fn random(&mut self, min_incl: u8, max_incl: u8) -> u8 {
+        // xorshift32
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+
+        let range = max_incl as u32 - min_incl as u32 + 1;
+        min_incl + ((x >> 8) % range) as u8
     }
 
-    pub fn update_st(&mut self) {
-        if self.cpu.sound > 0 { self.cpu.sound -= 1 }
+    // The only place the delay and sound timers count down, once per frame
+    fn tick_timers(&mut self) {
+        self.cpu.delay = self.cpu.delay.saturating_sub(1);
+        self.cpu.sound = self.cpu.sound.saturating_sub(1);
     }
 
     pub fn get_mnemonic(opcode: u16) -> String {
+        // Data, or an instruction this interpreter doesn't run
+        let unknown = || format!("DW {:#06x}", opcode);
         match (opcode & 0xF000) >> 12 {
 
             0x0 => {
@@ -613,7 +788,7 @@ impl Chip8 {
 
             0x4 => format!("SNE V{:1x}, {:#04x}", (opcode & 0x0F00) >> 8, opcode & 0x00FF),
 
-            0x5 => if opcode & 0x000F == 0 { format!("SE V{:1x}, V{:1x}", (opcode & 0x0F00) >> 8, (opcode & 0x00F0) >> 4) } else { panic!("Unrecognized {:2x}", opcode)},
+            0x5 => if opcode & 0x000F == 0 { format!("SE V{:1x}, V{:1x}", (opcode & 0x0F00) >> 8, (opcode & 0x00F0) >> 4) } else { unknown() },
 
             0x6 => format!("LD V{:1x}, {:#04x}", (opcode & 0x0F00) >> 8, opcode & 0x00FF),
 
@@ -630,11 +805,11 @@ impl Chip8 {
                     0x6 => format!("SHR V{:1x}", (opcode & 0x0F00) >> 8),
                     0x7 => format!("SUBN V{:1x}, V{:1x}", (opcode & 0x0F00) >> 8, (opcode & 0x00F0) >> 4),
                     0xE => format!("SHL V{:1x}", (opcode & 0x0F00) >> 8),
-                    _ => panic!("Impossible opcode {:2x}", opcode)
+                    _ => unknown()
                 }
             }
 
-            0x9 => if opcode & 0x000F == 0 { format!("SNE V{:1x}, V{:1x}", (opcode & 0x0F00) >> 8, (opcode & 0x00F0) >> 4) } else { panic!("Unrecognized opcode {:2x}", opcode) },
+            0x9 => if opcode & 0x000F == 0 { format!("SNE V{:1x}, V{:1x}", (opcode & 0x0F00) >> 8, (opcode & 0x00F0) >> 4) } else { unknown() },
 
             0xA => format!("LD I, {:#06x}", opcode & 0x0FFF),
 
@@ -648,7 +823,7 @@ impl Chip8 {
                 match opcode & 0x00FF {
                     0x9E => format!("SKP V{:1x}", (opcode & 0x0F00) >> 8),
                     0xA1 => format!("SKNP V{:1x}", (opcode & 0x0F00) >> 8),
-                    _ => panic!("Impossible opcode {:2x}",  opcode)
+                    _ => unknown()
                 }
             }
 
@@ -660,14 +835,15 @@ impl Chip8 {
                     0x18 => format!("LD ST, V{:1x}", (opcode & 0x0F00) >> 8),
                     0x1E => format!("ADD I, V{:1x}", (opcode & 0x0F00) >> 8),
                     0x29 => format!("LD F, V{:1x}", (opcode & 0x0F00) >> 8),
+                    0x30 => format!("LD HF, V{:1x}", (opcode & 0x0F00) >> 8),
                     0x33 => format!("LD B, V{:1x}", (opcode & 0x0F00) >> 8),
                     0x55 => format!("LD I, V{:1x}", (opcode & 0x0F00) >> 8),
                     0x65 => format!("LD V{:1x}, I", (opcode & 0x0F00) >> 8),
-                    _ => panic!("Impossible opcode {:2x}", opcode)
+                    _ => unknown()
                 }
             }
 
-            _ => { panic!("Impossible opcode {:2x}", opcode) }
+            _ => unknown()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use super::{Chip8, frontend::InputEvent};
+
+// Input recording for reproducible runs. One event per line:
+//   <frame> down|up <key>
+// with the key in hex; anything after '#' is a comment
+pub struct Movie {
+    events: Vec<(u64, InputEvent)>
+}
+
+impl Movie {
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut events = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() { continue }
+
+            let error = || format!("Line {}: expected '<frame> down|up <key>', found '{}'", number + 1, line);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let [frame, action, key] = parts[..] else { return Err(error()) };
+
+            let frame = frame.parse::<u64>().map_err(|_| error())?;
+            let key = u8::from_str_radix(key.trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|key| *key <= 0xF)
+                .ok_or_else(error)?;
+            let event = match action {
+                "down" => InputEvent::KeyDown(key),
+                "up" => InputEvent::KeyUp(key),
+                _ => return Err(error())
+            };
+            events.push((frame, event));
+        }
+        // Stable, so events on the same frame keep their order
+        events.sort_by_key(|(frame, _)| *frame);
+        Ok(Movie { events })
+    }
+
+    pub fn last_frame(&self) -> u64 {
+        self.events.last().map(|(frame, _)| *frame).unwrap_or(0)
+    }
+
+    pub fn apply(&self, frame: u64, chip: &mut Chip8) {
+        for (_, event) in self.events.iter().filter(|(at, _)| *at == frame) {
+            chip.apply_event(*event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod movie_test {
+    use super::{Movie, InputEvent};
+
+    #[test]
+    fn parse_events() {
+        let movie = Movie::parse("# start\n10 down 5\n\n4 down 0xa  # jump\n12 up 5\n").unwrap();
+        assert_eq!(movie.events, vec![
+            (4, InputEvent::KeyDown(0xA)),
+            (10, InputEvent::KeyDown(0x5)),
+            (12, InputEvent::KeyUp(0x5))
+        ]);
+        assert_eq!(movie.last_frame(), 12);
+    }
+
+    #[test]
+    fn reject_bad_lines() {
+        assert!(Movie::parse("10 press 5").is_err());
+        assert!(Movie::parse("10 down 10").is_err());
+        assert!(Movie::parse("ten down 1").is_err());
+        assert!(Movie::parse("10 down").is_err());
+    }
+
+    #[test]
+    fn apply_on_frame() {
+        let mut chip = super::Chip8::new();
+        let movie = Movie::parse("3 down 7").unwrap();
+
+        movie.apply(2, &mut chip);
+        assert!(chip.keypad.is_up(7));
+        movie.apply(3, &mut chip);
+        assert!(chip.keypad.is_down(7));
+    }
+}
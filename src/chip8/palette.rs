@@ -0,0 +1,43 @@
+// Named colour sets for the display. The first two colours are the off and on
+// pixels; the other two are there for XO-CHIP's second bit plane
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
+pub struct Palette {
+    pub name: String,
+    pub colors: [[u8; 3]; 4]
+}
+
+impl Palette {
+
+    pub fn new(name: &str, colors: [[u8; 3]; 4]) -> Self {
+        Palette { name: name.to_owned(), colors }
+    }
+
+    pub fn off(&self) -> [u8; 3] {
+        self.colors[0]
+    }
+
+    pub fn on(&self) -> [u8; 3] {
+        self.colors[1]
+    }
+
+    pub fn presets() -> Vec<Palette> {
+        vec![
+            Palette::new("classic", [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55]]),
+            Palette::new("green phosphor", [[0x0A, 0x1A, 0x0A], [0x33, 0xFF, 0x66], [0x1F, 0x99, 0x3D], [0x14, 0x5C, 0x26]]),
+            Palette::new("amber", [[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00], [0xB3, 0x7B, 0x00], [0x66, 0x46, 0x00]]),
+            Palette::new("lcd", [[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F], [0x30, 0x62, 0x30], [0x8B, 0xAC, 0x0F]]),
+            Palette::new("xo-chip", [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]])
+        ]
+    }
+
+    pub fn find<'a>(palettes: &'a [Palette], name: &str) -> Option<&'a Palette> {
+        palettes.iter().find(|palette| palette.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::presets().swap_remove(0)
+    }
+}
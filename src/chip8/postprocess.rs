@@ -0,0 +1,118 @@
+// Render stage between Display::screen and the frontend texture, hiding the
+// flicker caused by games erasing and redrawing sprites with XOR
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum Filter {
+    None,
+    // Lit pixels fade out, keeping this fraction of their intensity each frame
+    Phosphor(f32),
+    // A pixel is lit if it was lit in either of the last two frames
+    Blend
+}
+
+impl Filter {
+
+    pub const ALL: [Filter; 3] = [Filter::None, Filter::Phosphor(0.6), Filter::Blend];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::None => "none",
+            Filter::Phosphor(_) => "phosphor",
+            Filter::Blend => "blend"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Filter> {
+        Filter::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+}
+
+pub struct PostProcess {
+    pub filter: Filter,
+    previous: [[u8; 64]; 32],
+    output: [[f32; 64]; 32]
+}
+
+impl PostProcess {
+
+    pub fn new(filter: Filter) -> Self {
+        PostProcess {
+            filter,
+            previous: [[0; 64]; 32],
+            output: [[0.0; 64]; 32]
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = [[0; 64]; 32];
+        self.output = [[0.0; 64]; 32];
+    }
+
+    pub fn process(&mut self, screen: &[[u8; 64]; 32]) {
+        let pixels = self.output.iter_mut().flatten()
+            .zip(screen.iter().flatten())
+            .zip(self.previous.iter().flatten());
+
+        for ((out, pixel), previous) in pixels {
+            let lit = *pixel != 0;
+            *out = match self.filter {
+                Filter::None => if lit { 1.0 } else { 0.0 },
+                Filter::Phosphor(persistence) => if lit { 1.0 } else { *out * persistence },
+                Filter::Blend => if lit || *previous != 0 { 1.0 } else { 0.0 }
+            };
+        }
+        self.previous = *screen;
+    }
+
+    // Intensity of every pixel, from 0.0 (off) to 1.0 (on)
+    pub fn output(&self) -> &[[f32; 64]; 32] {
+        &self.output
+    }
+}
+
+pub fn mix(off: [u8; 3], on: [u8; 3], intensity: f32) -> [u8; 3] {
+    let channel = |i: usize| (off[i] as f32 + (on[i] as f32 - off[i] as f32) * intensity).round() as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+#[cfg(test)]
+mod postprocess_test {
+    use super::{Filter, PostProcess};
+
+    #[test]
+    fn blend_keeps_last_two_frames() {
+        let mut post = PostProcess::new(Filter::Blend);
+        let mut screen = [[0u8; 64]; 32];
+
+        screen[0][0] = 1;
+        post.process(&screen);
+        assert_eq!(post.output()[0][0], 1.0);
+
+        screen[0][0] = 0;
+        post.process(&screen);
+        assert_eq!(post.output()[0][0], 1.0);
+
+        post.process(&screen);
+        assert_eq!(post.output()[0][0], 0.0);
+    }
+
+    #[test]
+    fn phosphor_decays() {
+        let mut post = PostProcess::new(Filter::Phosphor(0.5));
+        let mut screen = [[0u8; 64]; 32];
+
+        screen[3][5] = 1;
+        post.process(&screen);
+        screen[3][5] = 0;
+        post.process(&screen);
+        assert_eq!(post.output()[3][5], 0.5);
+        post.process(&screen);
+        assert_eq!(post.output()[3][5], 0.25);
+    }
+
+    #[test]
+    fn mix_colors() {
+        assert_eq!(super::mix([0, 0, 0], [255, 255, 255], 1.0), [255, 255, 255]);
+        assert_eq!(super::mix([0, 100, 200], [200, 100, 0], 0.5), [100, 100, 100]);
+    }
+}
@@ -0,0 +1,109 @@
+// Behaviours that differ between CHIP-8 interpreters, kept across resets
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    // DRW waits for the vertical blank interrupt, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+    // LD Vx, K only continues once the key is released again, instead of as soon as it is pressed
+    pub wait_for_release: bool
+}
+
+impl Quirks {
+
+    pub fn new() -> Self {
+        Quirks {
+            display_wait: false,
+            wait_for_release: false
+        }
+    }
+
+    pub fn vip() -> Self {
+        Quirks {
+            display_wait: true,
+            wait_for_release: true
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::new()
+    }
+}
+
+#[cfg(test)]
+mod quirks_test {
+    use super::super::Chip8;
+
+    // LD I, 0x0000; DRW V0, V0, 1; DRW V0, V0, 1; JP 0x0206
+    const TWO_DRAWS: [u8; 8] = [0xA0, 0x00, 0xD0, 0x01, 0xD0, 0x01, 0x12, 0x06];
+
+    #[test]
+    fn draws_freely_without_display_wait() {
+        let mut chip = Chip8::new();
+        chip.insert_rom(TWO_DRAWS.to_vec()).unwrap();
+        chip.load();
+
+        chip.step_frame();
+        // The second DRW erased the first one
+        assert_eq!(chip.display.screen[0][0], 0);
+        assert_eq!(chip.cpu.read_pc(), 0x0206);
+    }
+
+    #[test]
+    fn display_wait_draws_once_per_frame() {
+        let mut chip = Chip8::new();
+        chip.quirks.display_wait = true;
+        chip.insert_rom(TWO_DRAWS.to_vec()).unwrap();
+        chip.load();
+
+        // LD I is the first instruction, so the first DRW waits for the next frame
+        chip.step_frame();
+        assert_eq!(chip.display.screen[0][0], 0);
+        assert_eq!(chip.cpu.read_pc(), 0x0202);
+
+        chip.step_frame();
+        assert_eq!(chip.display.screen[0][0], 1);
+        assert_eq!(chip.cpu.read_pc(), 0x0204);
+
+        chip.step_frame();
+        assert_eq!(chip.display.screen[0][0], 0);
+        assert_eq!(chip.cpu.read_pc(), 0x0206);
+    }
+
+    // LD V0, K; JP 0x0202
+    const KEY_WAIT: [u8; 4] = [0xF0, 0x0A, 0x12, 0x02];
+
+    #[test]
+    fn key_wait_ends_on_press() {
+        let mut chip = Chip8::new();
+        chip.insert_rom(KEY_WAIT.to_vec()).unwrap();
+        chip.load();
+        // Held before the wait starts, so it does not count
+        chip.key_down(0x3);
+
+        chip.step_frame();
+        assert!(chip.waiting_for_key);
+        chip.key_down(0xB);
+        chip.key_down(0x7);
+        chip.step_frame();
+        assert!(!chip.waiting_for_key);
+        assert_eq!(chip.cpu.v_registers[0], 0x7);
+    }
+
+    #[test]
+    fn key_wait_ends_on_release() {
+        let mut chip = Chip8::new();
+        chip.quirks.wait_for_release = true;
+        chip.insert_rom(KEY_WAIT.to_vec()).unwrap();
+        chip.load();
+
+        chip.step_frame();
+        chip.key_down(0xB);
+        chip.step_frame();
+        assert!(chip.waiting_for_key);
+        chip.key_up(0xB);
+        chip.step_frame();
+        assert!(!chip.waiting_for_key);
+        assert_eq!(chip.cpu.v_registers[0], 0xB);
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+use std::path::Path;
+
+// Room for a program in the 4K this interpreter runs
+pub const MAX_ROM_SIZE: usize = Platform::Chip8.max_rom_size();
+
+pub const EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "zip"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+
+    pub const ALL: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip-8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xo-chip"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Platform> {
+        Platform::ALL.into_iter().find(|platform| platform.name() == name)
+    }
+
+    // Room for a program between 0x200 and the end of the platform's memory
+    pub const fn max_rom_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000 - 0x200,
+            Platform::XoChip => 0x10000 - 0x200
+        }
+    }
+
+    // Only plain CHIP-8 runs for now, the others fault on their own instructions
+    pub fn is_supported(&self) -> bool {
+        *self == Platform::Chip8
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Platform> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ch8" | "c8" => Some(Platform::Chip8),
+            "sc8" => Some(Platform::SuperChip),
+            "xo8" => Some(Platform::XoChip),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Read(String),
+    UnknownExtension(String),
+    Empty,
+    TooLarge { size: usize, max: usize },
+    UnsupportedPlatform(Platform),
+    Archive(String),
+    InvalidArchive(String),
+    NoRomInArchive,
+    SeveralRomsInArchive(Vec<String>),
+    ArchivesUnsupported
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Read(e) => write!(f, "Could not read the ROM: {}", e),
+            RomError::UnknownExtension(extension) => write!(f, "'.{}' files are not ROMs, expected one of: .{}", extension, EXTENSIONS.join(", .")),
+            RomError::Empty => write!(f, "The ROM is empty"),
+            RomError::TooLarge { size, max } => write!(f, "The ROM is {} bytes, but at most {} bytes fit in memory", size, max),
+            RomError::UnsupportedPlatform(platform) => write!(f, "{} ROMs are not supported yet, only {} ones run", platform.name(), Platform::Chip8.name()),
+            RomError::Archive(e) => write!(f, "Could not open the archive: {}", e),
+            RomError::InvalidArchive(e) => write!(f, "Invalid zip archive: {}", e),
+            RomError::NoRomInArchive => write!(f, "The archive does not contain a ROM"),
+            RomError::SeveralRomsInArchive(names) => write!(f, "The archive contains more than one ROM: {}", names.join(", ")),
+            RomError::ArchivesUnsupported => write!(f, "This build cannot open .zip archives")
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+pub struct Rom {
+    pub name: String,
+    pub platform: Platform,
+    pub data: Vec<u8>
+}
+
+// Reads ROMs from files or archives, checking that they fit before they reach Chip8::insert_rom
+#[derive(Clone, Copy, Default)]
+pub struct RomLoader {
+    // Platform to check against, otherwise taken from the file extension
+    pub platform: Option<Platform>
+}
+
+impl RomLoader {
+
+    pub fn new() -> Self {
+        RomLoader { platform: None }
+    }
+
+    pub fn with_platform(platform: Platform) -> Self {
+        RomLoader { platform: Some(platform) }
+    }
+
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> Result<Rom, RomError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| RomError::Read(format!("{}: {}", path.display(), e)))?;
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        self.load_bytes(&name, data)
+    }
+
+    pub fn load_bytes(&self, name: &str, data: Vec<u8>) -> Result<Rom, RomError> {
+        // Archives are recognised by their contents, whatever they are called, and
+        // anything called .zip is one, so that a broken archive is reported as such
+        let zip_name = Path::new(name).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+        if zip_name || data.starts_with(b"PK\x03\x04") {
+            let (inner, data) = extract_single_rom(&data)?;
+            return self.check(&inner, data);
+        }
+        self.check(name, data)
+    }
+
+    fn check(&self, name: &str, data: Vec<u8>) -> Result<Rom, RomError> {
+        let extension = Path::new(name).extension().map(|extension| extension.to_string_lossy().into_owned());
+        let platform = match (self.platform, &extension) {
+            (Some(platform), _) => platform,
+            // Files without an extension are taken to be plain CHIP-8
+            (None, None) => Platform::Chip8,
+            (None, Some(extension)) => Platform::from_extension(extension)
+                .ok_or_else(|| RomError::UnknownExtension(extension.clone()))?
+        };
+
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        check_size(&data, platform)?;
+        if !platform.is_supported() {
+            return Err(RomError::UnsupportedPlatform(platform));
+        }
+        Ok(Rom { name: name.to_owned(), platform, data })
+    }
+}
+
+// Whether a program fits in the platform's memory after 0x200
+pub fn check_size(data: &[u8], platform: Platform) -> Result<(), RomError> {
+    let max = platform.max_rom_size();
+    if data.len() > max {
+        return Err(RomError::TooLarge { size: data.len(), max });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+fn is_rom_name(name: &str) -> bool {
+    Path::new(name).extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(Platform::from_extension)
+        .is_some()
+}
+
+#[cfg(feature = "zip")]
+fn extract_single_rom(data: &[u8]) -> Result<(String, Vec<u8>), RomError> {
+    use std::io::Read;
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(|e| RomError::InvalidArchive(e.to_string()))?;
+    let names: Vec<String> = archive.file_names()
+        .filter(|name| !name.ends_with('/') && is_rom_name(name))
+        .map(String::from)
+        .collect();
+    let name = match &names[..] {
+        [] => return Err(RomError::NoRomInArchive),
+        [name] => name.clone(),
+        _ => return Err(RomError::SeveralRomsInArchive(names))
+    };
+
+    let mut file = archive.by_name(&name).map_err(|e| RomError::Archive(e.to_string()))?;
+    // Never inflate more than a ROM for any platform could possibly be
+    let max = Platform::XoChip.max_rom_size();
+    let mut rom = vec![];
+    file.by_ref().take(max as u64 + 1).read_to_end(&mut rom).map_err(|e| RomError::Archive(e.to_string()))?;
+    Ok((name, rom))
+}
+
+#[cfg(not(feature = "zip"))]
+fn extract_single_rom(_data: &[u8]) -> Result<(String, Vec<u8>), RomError> {
+    Err(RomError::ArchivesUnsupported)
+}
+
+#[cfg(test)]
+mod rom_test {
+    use super::{check_size, Platform, RomError, RomLoader, MAX_ROM_SIZE};
+
+    #[test]
+    fn platform_from_extension() {
+        let loader = RomLoader::new();
+        assert_eq!(loader.load_bytes("pong.ch8", vec![0x12, 0x00]).unwrap().platform, Platform::Chip8);
+        assert_eq!(loader.load_bytes("PONG.C8", vec![0x12, 0x00]).unwrap().platform, Platform::Chip8);
+        assert_eq!(loader.load_bytes("PONG", vec![0x12, 0x00]).unwrap().platform, Platform::Chip8);
+        assert!(matches!(loader.load_bytes("notes.txt", vec![0x12, 0x00]), Err(RomError::UnknownExtension(_))));
+
+        // An explicit platform wins over the extension
+        let loader = RomLoader::with_platform(Platform::XoChip);
+        assert!(matches!(loader.load_bytes("pong.ch8", vec![0x12, 0x00]), Err(RomError::UnsupportedPlatform(Platform::XoChip))));
+    }
+
+    #[test]
+    fn unsupported_platforms() {
+        let loader = RomLoader::new();
+        let error = loader.load_bytes("car.sc8", vec![0x12, 0x00]).err().unwrap();
+        assert!(matches!(error, RomError::UnsupportedPlatform(Platform::SuperChip)));
+        assert_eq!(error.to_string(), "schip ROMs are not supported yet, only chip-8 ones run");
+        assert!(matches!(loader.load_bytes("flight.xo8", vec![0x12, 0x00]), Err(RomError::UnsupportedPlatform(Platform::XoChip))));
+    }
+
+    #[test]
+    fn size_limits() {
+        let loader = RomLoader::new();
+        assert!(matches!(loader.load_bytes("a.ch8", vec![]), Err(RomError::Empty)));
+        assert!(loader.load_bytes("a.ch8", vec![0; MAX_ROM_SIZE]).is_ok());
+
+        let error = loader.load_bytes("a.ch8", vec![0; MAX_ROM_SIZE + 1]).err().unwrap();
+        assert!(matches!(error, RomError::TooLarge { size: 3585, max: 3584 }));
+        assert_eq!(error.to_string(), "The ROM is 3585 bytes, but at most 3584 bytes fit in memory");
+
+        // The core checks too, for ROMs that don't come through the loader
+        let mut chip = super::super::Chip8::new();
+        assert!(matches!(chip.insert_rom(vec![0; MAX_ROM_SIZE + 1]), Err(RomError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn size_limits_per_platform() {
+        assert!(check_size(&[0; 3584], Platform::Chip8).is_ok());
+        assert!(matches!(check_size(&[0; 3585], Platform::Chip8), Err(RomError::TooLarge { size: 3585, max: 3584 })));
+        assert!(check_size(&[0; 3584], Platform::SuperChip).is_ok());
+        assert!(matches!(check_size(&[0; 3585], Platform::SuperChip), Err(RomError::TooLarge { size: 3585, max: 3584 })));
+        // XO-CHIP programs fill its 64K address space
+        assert!(check_size(&[0; 65024], Platform::XoChip).is_ok());
+        assert!(matches!(check_size(&[0; 65025], Platform::XoChip), Err(RomError::TooLarge { size: 65025, max: 65024 })));
+
+        // Too large is reported before unsupported
+        let loader = RomLoader::new();
+        assert!(matches!(loader.load_bytes("a.xo8", vec![0; 65025]), Err(RomError::TooLarge { max: 65024, .. })));
+    }
+
+    #[cfg(feature = "zip")]
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn single_rom_archive() {
+        let loader = RomLoader::new();
+        let archive = zip(&[("readme.txt", b"hello"), ("games/tank.ch8", &[0x12, 0x00])]);
+        let rom = loader.load_bytes("tank.zip", archive).unwrap();
+        assert_eq!((rom.name.as_str(), rom.platform, rom.data), ("games/tank.ch8", Platform::Chip8, vec![0x12, 0x00]));
+        let archive = zip(&[("car.sc8", &[0x12, 0x00])]);
+        assert!(matches!(loader.load_bytes("car.zip", archive), Err(RomError::UnsupportedPlatform(Platform::SuperChip))));
+
+        let archive = zip(&[("a.ch8", &[0x00]), ("b.ch8", &[0x00])]);
+        assert!(matches!(loader.load_bytes("two.zip", archive), Err(RomError::SeveralRomsInArchive(_))));
+        let archive = zip(&[("readme.txt", b"hello")]);
+        assert!(matches!(loader.load_bytes("none.zip", archive), Err(RomError::NoRomInArchive)));
+        assert!(matches!(loader.load_bytes("broken.zip", b"not a zip".to_vec()), Err(RomError::InvalidArchive(_))));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use super::{config::Config, quirks::Quirks};
+
+const BUNDLED: &str = include_str!("roms.toml");
+
+// Quirks a ROM needs; the ones left out keep whatever the user chose
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct QuirkOverrides {
+    pub display_wait: Option<bool>,
+    pub wait_for_release: Option<bool>
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Option<String>,
+    pub cycles_per_frame: Option<u32>,
+    pub quirks: QuirkOverrides,
+    pub palette: Option<String>,
+    // What each keypad key does, by its hex digit
+    pub keys: BTreeMap<String, String>
+}
+
+impl RomInfo {
+
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        if let Some(display_wait) = self.quirks.display_wait {
+            quirks.display_wait = display_wait;
+        }
+        if let Some(wait_for_release) = self.quirks.wait_for_release {
+            quirks.wait_for_release = wait_for_release;
+        }
+    }
+
+    pub fn key_hints(&self) -> Vec<String> {
+        self.keys.iter().map(|(key, hint)| format!("{}: {}", key.to_uppercase(), hint)).collect()
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct RomFile {
+    #[serde(default)]
+    roms: HashMap<String, RomInfo>
+}
+
+pub struct RomDb {
+    roms: HashMap<String, RomInfo>
+}
+
+impl RomDb {
+
+    pub fn parse(text: &str) -> Result<RomDb, String> {
+        let file: RomFile = toml::from_str(text).map_err(|e| format!("Invalid ROM database: {}", e))?;
+        let roms = file.roms.into_iter().map(|(hash, info)| (hash.to_lowercase(), info)).collect();
+        Ok(RomDb { roms })
+    }
+
+    pub fn bundled() -> RomDb {
+        RomDb::parse(BUNDLED).expect("bundled ROM database is valid")
+    }
+
+    // The bundled database along with the user's own roms.toml, if there is one
+    pub fn load() -> RomDb {
+        let mut db = RomDb::bundled();
+        let Some(path) = Config::dir().map(|dir| dir.join("roms.toml")) else { return db };
+        if !path.exists() {
+            return db;
+        }
+        match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| RomDb::parse(&text)) {
+            Ok(user) => db.roms.extend(user.roms),
+            Err(e) => eprintln!("{}: {}", path.display(), e)
+        }
+        db
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1_hex(rom))
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod romdb_test {
+    use super::{RomDb, Quirks};
+
+    #[test]
+    fn sha1_of_rom() {
+        assert_eq!(super::sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn bundled_parses() {
+        RomDb::bundled();
+    }
+
+    #[test]
+    fn bundled_knows_maze() {
+        const MAZE: [u8; 34] = [
+            0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04,
+            0x30, 0x40, 0x12, 0x00, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00,
+            0x12, 0x18, 0x80, 0x40, 0x20, 0x10, 0x20, 0x40, 0x80, 0x10
+        ];
+        let db = RomDb::bundled();
+        let info = db.lookup(&MAZE).unwrap();
+        assert_eq!(info.title, "Maze");
+        assert_eq!(info.cycles_per_frame, Some(15));
+    }
+
+    #[test]
+    fn lookup_by_hash() {
+        let db = RomDb::parse(r#"
+            [roms.A9993E364706816ABA3E25717850C26C9CD0D89D]
+            title = "abc"
+            cycles_per_frame = 15
+            quirks = { display_wait = true }
+            keys = { "5" = "up", "a" = "fire" }
+        "#).unwrap();
+
+        assert!(db.lookup(b"abd").is_none());
+        let info = db.lookup(b"abc").unwrap();
+        assert_eq!(info.title, "abc");
+        assert_eq!(info.cycles_per_frame, Some(15));
+        assert_eq!(info.palette, None);
+        assert_eq!(info.key_hints(), vec!["5: up", "A: fire"]);
+
+        let mut quirks = Quirks::new();
+        info.apply_quirks(&mut quirks);
+        assert!(quirks.display_wait);
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        assert!(RomDb::parse("[roms.abc]\ncycles_per_frame = \"fast\"").is_err());
+    }
+}
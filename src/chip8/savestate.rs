@@ -0,0 +1,157 @@
+use super::{Chip8, stack::MAX_STACK_DEPTH};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 8;
+
+pub const STATE_SIZE: usize = 4 + 1   // magic, version
+    + 16 + 2 + 1 + 1 + 2 + 1          // cpu
+    + 2 * MAX_STACK_DEPTH             // isolated stack
+    + 0x1000                          // memory
+    + 64 * 32                         // display
+    + 16 + 2 + 2 + 2 + 8              // keypad, presses, releases, unseen presses, frame
+    + 2 + 2 + 1 + 1 + 2               // opcode, resume_from, waiting_for_key, waiting_for_vblank, cycles
+    + 4;                              // rng
+
+impl Chip8 {
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+
+        state.extend_from_slice(&self.cpu.v_registers);
+        state.extend_from_slice(&self.cpu.i_register.to_be_bytes());
+        state.push(self.cpu.delay);
+        state.push(self.cpu.sound);
+        state.extend_from_slice(&self.cpu.program_counter.to_be_bytes());
+        state.push(self.cpu.stack_pointer);
+        for entry in self.stack.entries {
+            state.extend_from_slice(&entry.to_be_bytes());
+        }
+
+        state.extend_from_slice(&self.memory.address_space);
+        for row in self.display.screen.iter() {
+            state.extend_from_slice(row);
+        }
+        state.extend_from_slice(&self.keypad.keys);
+        state.extend_from_slice(&self.keypad.presses.to_be_bytes());
+        state.extend_from_slice(&self.keypad.releases.to_be_bytes());
+        state.extend_from_slice(&self.keypad.unseen_presses.to_be_bytes());
+        state.extend_from_slice(&self.keypad.frame.to_be_bytes());
+
+        state.extend_from_slice(&self.opcode.to_be_bytes());
+        state.extend_from_slice(&self.resume_from.to_be_bytes());
+        state.push(self.waiting_for_key as u8);
+        state.push(self.waiting_for_vblank as u8);
+        state.extend_from_slice(&(self.cycles as u16).to_be_bytes());
+        state.extend_from_slice(&self.rng_state.to_be_bytes());
+
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != STATE_SIZE || &state[0..4] != MAGIC {
+            return Err("Not a save state".to_owned());
+        }
+        if state[4] != VERSION {
+            return Err(format!("Unsupported save state version {}", state[4]));
+        }
+
+        let mut reader = StateReader { state, pos: 5 };
+
+        self.cpu.v_registers.copy_from_slice(reader.take(16));
+        self.cpu.i_register = reader.u16();
+        self.cpu.delay = reader.u8();
+        self.cpu.sound = reader.u8();
+        self.cpu.program_counter = reader.u16();
+        self.cpu.stack_pointer = reader.u8();
+        for entry in self.stack.entries.iter_mut() {
+            *entry = reader.u16();
+        }
+
+        self.memory.address_space.copy_from_slice(reader.take(0x1000));
+        for row in self.display.screen.iter_mut() {
+            row.copy_from_slice(reader.take(64));
+        }
+        self.keypad.keys.copy_from_slice(reader.take(16));
+        self.keypad.presses = reader.u16();
+        self.keypad.releases = reader.u16();
+        self.keypad.unseen_presses = reader.u16();
+        self.keypad.frame = reader.u64();
+
+        self.opcode = reader.u16();
+        self.resume_from = reader.u16();
+        self.waiting_for_key = reader.u8() != 0;
+        self.waiting_for_vblank = reader.u8() != 0;
+        self.cycles = reader.u16() as usize;
+        self.rng_state = reader.u32();
+
+        self.new_draw = true;
+        Ok(())
+    }
+}
+
+struct StateReader<'a> {
+    state: &'a [u8],
+    pos: usize
+}
+
+impl<'a> StateReader<'a> {
+
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let slice = &self.state[self.pos..self.pos + len];
+        self.pos += len;
+        slice
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        let bytes = self.take(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn u64(&mut self) -> u64 {
+        let bytes = self.take(8);
+        u64::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> u32 {
+        let bytes = self.take(4);
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+#[cfg(test)]
+mod savestate_test {
+    use super::super::Chip8;
+
+    #[test]
+    fn save_load_roundtrip() {
+        let mut chip = Chip8::new();
+        // LD V3, 0x42; LD I, 0x0000; DRW V0, V0, 5; JP 0x0206
+        chip.insert_rom(vec![0x63, 0x42, 0xA0, 0x00, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+        chip.load();
+        chip.step_frame();
+
+        let state = chip.save_state();
+        assert_eq!(state.len(), super::STATE_SIZE);
+
+        let mut other = Chip8::new();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.cpu.v_registers, chip.cpu.v_registers);
+        assert_eq!(other.cpu.i_register, 0x0000);
+        assert_eq!(other.cpu.read_pc(), chip.cpu.read_pc());
+        assert_eq!(other.memory.address_space, chip.memory.address_space);
+        assert_eq!(other.display.screen, chip.display.screen);
+        assert_eq!(other.save_state(), state);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut chip = Chip8::new();
+        assert!(chip.load_state(&[0u8; 16]).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+
+use super::{Chip8, memory::Memory};
+
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+pub const VIP_STACK_DEPTH: usize = 12;
+// Also keeps a memory-mapped stack clear of the program at 0x200
+pub const MAX_STACK_DEPTH: usize = 64;
+// Where a memory-mapped stack starts, so its deepest level ends where the program is loaded
+pub const MEMORY_STACK_BASE: u16 = 0x0200 - 2 * MAX_STACK_DEPTH as u16;
+
+// Where return addresses are kept
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StackStorage {
+    // In memory, as on the COSMAC VIP, where ROMs can read and overwrite it
+    Memory,
+    // Out of reach of the program
+    Isolated
+}
+
+impl StackStorage {
+
+    pub const ALL: [StackStorage; 2] = [StackStorage::Memory, StackStorage::Isolated];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StackStorage::Memory => "memory",
+            StackStorage::Isolated => "isolated"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StackStorage> {
+        StackStorage::ALL.into_iter().find(|storage| storage.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StackError {
+    // A CALL with every level already used
+    Overflow { depth: usize },
+    // A RET outside of any subroutine
+    Underflow,
+    // A RET with the stack pointer past the deepest level there can be, like from a damaged saved state
+    Corrupt { sp: usize }
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackError::Overflow { depth } => write!(f, "Stack overflow, more than {} nested CALLs", depth),
+            StackError::Underflow => write!(f, "Stack underflow, RET outside of a subroutine"),
+            StackError::Corrupt { sp } => write!(f, "Corrupt stack pointer {}, past the deepest level {}", sp, MAX_STACK_DEPTH)
+        }
+    }
+}
+
+impl std::error::Error for StackError {}
+
+// The stack pointer is the CPU's, counting the levels in use
+pub struct Stack {
+    // Levels available, kept across resets like the quirks
+    pub depth: usize,
+    pub storage: StackStorage,
+    pub entries: [u16; MAX_STACK_DEPTH]
+}
+
+impl Stack {
+
+    pub fn new() -> Self {
+        Stack {
+            depth: DEFAULT_STACK_DEPTH,
+            storage: StackStorage::Memory,
+            entries: [0; MAX_STACK_DEPTH]
+        }
+    }
+
+    pub fn vip() -> Self {
+        Stack { depth: VIP_STACK_DEPTH, ..Stack::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = [0; MAX_STACK_DEPTH];
+    }
+
+    fn read(&self, memory: &Memory, level: usize) -> u16 {
+        match self.storage {
+            StackStorage::Memory => memory.read_u16(MEMORY_STACK_BASE + 2 * level as u16),
+            StackStorage::Isolated => self.entries[level]
+        }
+    }
+
+    fn write(&mut self, memory: &mut Memory, level: usize, value: u16) {
+        match self.storage {
+            StackStorage::Memory => memory.write_u16(MEMORY_STACK_BASE + 2 * level as u16, value),
+            StackStorage::Isolated => self.entries[level] = value
+        }
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Stack::new()
+    }
+}
+
+impl Chip8 {
+
+    pub fn stack_depth(&self) -> usize {
+        self.cpu.get_sp() as usize
+    }
+
+    // Level 0 is the outermost call
+    pub fn stack_entry(&self, level: usize) -> u16 {
+        self.stack.read(&self.memory, level)
+    }
+
+    pub fn stack_push(&mut self, value: u16) -> Result<(), StackError> {
+        let level = self.stack_depth();
+        let depth = self.stack.depth.min(MAX_STACK_DEPTH);
+        if level >= depth {
+            return Err(StackError::Overflow { depth });
+        }
+        self.stack.write(&mut self.memory, level, value);
+        self.cpu.set_sp(level as u8 + 1);
+        Ok(())
+    }
+
+    pub fn stack_pop(&mut self) -> Result<u16, StackError> {
+        let level = self.stack_depth().checked_sub(1).ok_or(StackError::Underflow)?;
+        // A saved state can hold more levels than the stack now has
+        if level >= MAX_STACK_DEPTH {
+            return Err(StackError::Corrupt { sp: level + 1 });
+        }
+        self.cpu.set_sp(level as u8);
+        Ok(self.stack.read(&self.memory, level))
+    }
+}
+
+#[cfg(test)]
+mod stack_test {
+    use super::super::{Chip8, fault::Fault};
+    use super::{StackError, StackStorage, MAX_STACK_DEPTH, MEMORY_STACK_BASE, VIP_STACK_DEPTH, Stack};
+
+    // CALL 0x0200, over and over
+    const RECURSE: [u8; 2] = [0x22, 0x00];
+
+    #[test]
+    fn overflow_stops_the_program() {
+        let mut chip = Chip8::new();
+        chip.stack = Stack::vip();
+        chip.insert_rom(RECURSE.to_vec()).unwrap();
+        chip.load();
+
+        for _ in 0..VIP_STACK_DEPTH {
+            chip.cycle();
+        }
+        assert_eq!(chip.fault, None);
+        chip.cycle();
+        assert_eq!(chip.fault, Some(Fault::Stack(StackError::Overflow { depth: VIP_STACK_DEPTH })));
+        assert_eq!(chip.stack_depth(), VIP_STACK_DEPTH);
+
+        // Nothing runs any more, but frames still end
+        chip.step_frame();
+        assert_eq!(chip.stack_depth(), VIP_STACK_DEPTH);
+    }
+
+    #[test]
+    fn underflow_is_an_error() {
+        // RET
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0x00, 0xEE]).unwrap();
+        chip.load();
+
+        chip.cycle();
+        assert_eq!(chip.fault, Some(Fault::Stack(StackError::Underflow)));
+        assert_eq!(chip.cpu.read_pc(), 0x0202);
+    }
+
+    #[test]
+    fn corrupt_stack_pointer() {
+        // RET
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0x00, 0xEE]).unwrap();
+        chip.load();
+        chip.cpu.set_sp(MAX_STACK_DEPTH as u8 + 6);
+
+        chip.cycle();
+        assert_eq!(chip.fault, Some(Fault::Stack(StackError::Corrupt { sp: 70 })));
+        assert_eq!(chip.fault.unwrap().to_string(), "Corrupt stack pointer 70, past the deepest level 64");
+    }
+
+    #[test]
+    fn storage_choice() {
+        for storage in StackStorage::ALL {
+            let mut chip = Chip8::new();
+            chip.stack.storage = storage;
+            chip.stack_push(0x0345).unwrap();
+
+            let in_memory = chip.memory.read_u16(MEMORY_STACK_BASE) == 0x0345;
+            assert_eq!(in_memory, storage == StackStorage::Memory);
+            // A ROM writing over the stack only changes a memory-mapped one
+            chip.memory.write_u16(MEMORY_STACK_BASE, 0x0456);
+            let expected = if in_memory { 0x0456 } else { 0x0345 };
+            assert_eq!(chip.stack_pop(), Ok(expected));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Labels for addresses, as written by assemblers. One per line:
+//   <address> <name>
+// with the address in hex; anything after '#' is a comment
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>
+}
+
+impl Symbols {
+
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut labels = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() { continue }
+
+            let error = || format!("Line {}: expected '<address> <name>', found '{}'", number + 1, line);
+            let (address, name) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let address = u16::from_str_radix(address.trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|address| *address < 0x1000)
+                .ok_or_else(error)?;
+            labels.insert(address, name.trim().to_owned());
+        }
+        Ok(Symbols { labels })
+    }
+
+    // The .sym file next to the ROM, if there is one
+    pub fn load_for(rom: &Path) -> Result<Option<Symbols>, String> {
+        let path = rom.with_extension("sym");
+        if !path.exists() { return Ok(None) }
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Symbols::parse(&text).map(Some)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|name| name.as_str())
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| label.as_str() == name).map(|(address, _)| *address)
+    }
+
+    // The address, followed by its label if it has one
+    pub fn describe(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(name) => format!("{:#06x} ({})", address, name),
+            None => format!("{:#06x}", address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod symbols_test {
+    use super::Symbols;
+
+    #[test]
+    fn parse_labels() {
+        let symbols = Symbols::parse("# made by hand\n0x200 main\n\n2a4 draw_player  # sprite\n").unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.label(0x2A4), Some("draw_player"));
+        assert_eq!(symbols.address_of("main"), Some(0x200));
+        assert_eq!(symbols.describe(0x200), "0x0200 (main)");
+        assert_eq!(symbols.describe(0x202), "0x0202");
+    }
+
+    #[test]
+    fn reject_bad_lines() {
+        assert!(Symbols::parse("main\n").is_err());
+        assert!(Symbols::parse("0x1000 outside\n").is_err());
+        assert!(Symbols::parse("zz main\n").is_err());
+    }
+}
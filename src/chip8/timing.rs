@@ -0,0 +1,273 @@
+use super::cpu::CPU;
+
+// How long instructions take, and so where frames end
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timing {
+    // Every instruction takes the same time, Chip8::cycles_per_frame of them per frame
+    Fixed,
+    // Every instruction takes as many machine cycles as in the COSMAC VIP interpreter
+    Vip
+}
+
+impl Timing {
+
+    pub const ALL: [Timing; 2] = [Timing::Fixed, Timing::Vip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Timing::Fixed => "fixed",
+            Timing::Vip => "vip"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Timing> {
+        Timing::ALL.into_iter().find(|timing| timing.name() == name)
+    }
+}
+
+// How fast frames go by compared to the 60 Hz of the real machine
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Speed {
+    Normal,
+    // N times as many frames per second
+    Fast(u32),
+    // As many frames as the host can run
+    Uncapped,
+    // One frame every N sixtieths of a second
+    Slow(u32)
+}
+
+impl Speed {
+
+    pub const FAST_FORWARD: Speed = Speed::Fast(4);
+    pub const SLOW_MOTION: Speed = Speed::Slow(4);
+
+    // Seconds between two frames, None if they are not paced at all
+    pub fn frame_time(&self) -> Option<f64> {
+        match *self {
+            Speed::Normal => Some(1.0 / 60.0),
+            Speed::Fast(n) => Some(1.0 / (60.0 * n.max(1) as f64)),
+            Speed::Uncapped => None,
+            Speed::Slow(n) => Some(n.max(1) as f64 / 60.0)
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Speed::Normal => "1x".to_owned(),
+            Speed::Fast(n) => format!("{}x", n),
+            Speed::Uncapped => "uncapped".to_owned(),
+            Speed::Slow(n) => format!("1/{}x", n)
+        }
+    }
+}
+
+// Most a frontend catches up on after falling behind, time it held the emulator for
+// (like a paused debugger) beyond that is lost instead of run through at full speed
+pub const MAX_CATCH_UP: f64 = 0.25;
+
+// The 1802 runs at 1.7609 MHz with 8 clocks per machine cycle, so a 60 Hz frame is 3668 machine cycles
+pub const VIP_CYCLES_PER_FRAME: usize = 3668;
+// Taken every frame by the 1861's DMA, 128 lines of 8 bytes, and the interrupt routine driving it
+pub const VIP_DISPLAY_CYCLES: usize = 1024 + 80;
+// What is left for the interpreter
+pub const VIP_INTERPRETER_CYCLES: usize = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+
+// Fetching and dispatching an instruction
+const FETCH: usize = 40;
+// Extra cycles when a skip instruction skips
+pub const VIP_SKIP_CYCLES: usize = 4;
+
+// Machine cycles an instruction takes on the VIP, not counting a skip being taken.
+// The counts come from the interpreter's code and are rounded; DRW and FX33
+// depend on their operands, the rest are constant
+pub fn vip_cycles(opcode: u16, cpu: &CPU) -> usize {
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let n = (opcode & 0x000F) as usize;
+
+    FETCH + match opcode >> 12 {
+        0x0 => match opcode & 0x0FFF {
+            0x0E0 => 3078,
+            0x0EE => 10,
+            _ => 20
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10,
+        0x5 | 0x9 => 14,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => if n == 0 { 12 } else { 44 },
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // Every row costs more the further the sprite has to be shifted inside its byte
+        0xD => 68 + n * (46 + 20 * (cpu.v_registers[x] as usize & 7)),
+        0xE => 14,
+        0xF => match opcode & 0x00FF {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 18,
+            0x1E | 0x29 | 0x30 => 16,
+            0x33 => {
+                let value = cpu.v_registers[x] as usize;
+                80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            },
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 0
+        },
+        _ => 0
+    }
+}
+
+pub fn is_skip(opcode: u16) -> bool {
+    matches!(opcode >> 12, 0x3 | 0x4 | 0x5 | 0x9 | 0xE)
+}
+
+#[cfg(test)]
+mod timing_test {
+    use super::super::{Chip8, timing::{self, Speed, Timing}};
+
+    #[test]
+    fn drw_depends_on_position() {
+        let mut chip = Chip8::new();
+        chip.cpu.v_registers[0] = 8;
+        let aligned = timing::vip_cycles(0xD005, &chip.cpu);
+        chip.cpu.v_registers[0] = 11;
+        let shifted = timing::vip_cycles(0xD005, &chip.cpu);
+
+        assert!(shifted > aligned);
+        assert!(timing::vip_cycles(0xD00F, &chip.cpu) > shifted);
+    }
+
+    #[test]
+    fn frames_follow_the_clock() {
+        // ADD V0, 1; JP 0x200
+        let mut chip = Chip8::new();
+        chip.timing = Timing::Vip;
+        chip.insert_rom(vec![0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip.load();
+
+        chip.step_frame();
+        // Each loop is 50 + 52 machine cycles
+        let loops = chip.cpu.v_registers[0] as usize;
+        assert_eq!(loops, timing::VIP_INTERPRETER_CYCLES / 102 + 1);
+
+        // CLS takes most of a frame by itself
+        let mut chip = Chip8::new();
+        chip.timing = Timing::Vip;
+        chip.insert_rom(vec![0x00, 0xE0, 0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip.load();
+        chip.step_frame();
+        assert!(chip.cpu.v_registers[0] < loops as u8 / 4);
+    }
+
+    #[test]
+    fn cycles_per_frame_sets_frame_length() {
+        // ADD V0, 1; JP 0x200
+        let mut chip = Chip8::new();
+        chip.cycles_per_frame = 20;
+        chip.insert_rom(vec![0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip.load();
+
+        chip.step_frame();
+        assert_eq!(chip.cpu.v_registers[0], 10);
+    }
+
+    #[test]
+    fn speed_frame_time() {
+        assert_eq!(Speed::Normal.frame_time(), Some(1.0 / 60.0));
+        assert_eq!(Speed::Fast(4).frame_time(), Some(1.0 / 240.0));
+        assert_eq!(Speed::Slow(2).frame_time(), Some(1.0 / 30.0));
+        assert_eq!(Speed::Uncapped.frame_time(), None);
+    }
+
+    #[test]
+    fn delay_counts_at_60hz_for_any_cycles_per_frame() {
+        for (timing, cycles_per_frame) in [(Timing::Fixed, 1), (Timing::Fixed, 9), (Timing::Fixed, 1000), (Timing::Vip, 9)] {
+            // JP 0x200
+            let mut chip = Chip8::new();
+            chip.timing = timing;
+            chip.cycles_per_frame = cycles_per_frame;
+            chip.insert_rom(vec![0x12, 0x00]).unwrap();
+            chip.load();
+            chip.cpu.delay = 60;
+            chip.cpu.sound = 45;
+
+            for _ in 0..30 {
+                chip.step_frame();
+            }
+            assert_eq!(chip.cpu.delay, 30, "{:?} at {} cycles per frame", timing, cycles_per_frame);
+            assert_eq!(chip.cpu.sound, 15);
+            for _ in 0..30 {
+                chip.step_frame();
+            }
+            assert_eq!(chip.cpu.delay, 0);
+            assert_eq!(chip.cpu.sound, 0);
+        }
+    }
+
+    #[test]
+    fn timers_run_while_waiting_for_key() {
+        // LD V0, K
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0xF0, 0x0A]).unwrap();
+        chip.load();
+        chip.cpu.delay = 10;
+
+        for _ in 0..4 {
+            chip.step_frame();
+        }
+        assert!(chip.waiting_for_key);
+        assert_eq!(chip.cpu.delay, 6);
+    }
+
+    #[test]
+    fn single_steps_count_towards_the_frame() {
+        // JP 0x200
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0x12, 0x00]).unwrap();
+        chip.load();
+        chip.cpu.delay = 3;
+
+        for _ in 0..8 {
+            chip.fetch();
+            assert!(!chip.execute());
+        }
+        assert!(chip.cycle());
+        assert_eq!(chip.cpu.delay, 2);
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame() {
+        // LD V0, 30; LD DT, V0; JP 0x204
+        let mut chip = Chip8::new();
+        chip.timing = Timing::Vip;
+        chip.insert_rom(vec![0x60, 0x1E, 0xF0, 0x15, 0x12, 0x04]).unwrap();
+        chip.load();
+
+        for _ in 0..10 {
+            chip.step_frame();
+        }
+        assert_eq!(chip.cpu.delay, 20);
+    }
+
+    #[test]
+    fn frames_are_paced_in_real_time() {
+        // JP 0x200
+        let mut chip = Chip8::new();
+        chip.insert_rom(vec![0x12, 0x00]).unwrap();
+        chip.load();
+
+        let start = std::time::Instant::now();
+        let mut frames = 0;
+        chip.run_with_callback_last(|chip| {
+            if chip.vblank {
+                chip.vblank = false;
+                frames += 1;
+            }
+            chip.exit = start.elapsed().as_secs_f64() >= 0.2;
+        });
+        // 12 at 60 Hz, with room for a slow machine
+        assert!((6..=13).contains(&frames), "{} frames in 0.2 s", frames);
+    }
+}
@@ -0,0 +1,151 @@
+// Placement of the 64x32 screen inside a window of any size, keeping its 2:1 aspect ratio.
+// Whatever is left of the window around it is letterboxed by the frontend
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scaling {
+    // Largest whole multiple of the screen size, so every pixel is the same size
+    Integer,
+    // As large as the window allows
+    Fit
+}
+
+impl Scaling {
+
+    pub const ALL: [Scaling; 2] = [Scaling::Integer, Scaling::Fit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaling::Integer => "integer",
+            Scaling::Fit => "fit"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        Scaling::ALL.into_iter().find(|scaling| scaling.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Overlay {
+    None,
+    // A line between every pair of pixels
+    Grid,
+    // A line under every row of pixels
+    Scanlines
+}
+
+impl Overlay {
+
+    pub const ALL: [Overlay; 3] = [Overlay::None, Overlay::Grid, Overlay::Scanlines];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overlay::None => "none",
+            Overlay::Grid => "grid",
+            Overlay::Scanlines => "scanlines"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Overlay> {
+        Overlay::ALL.into_iter().find(|overlay| overlay.name() == name)
+    }
+}
+
+// Below this many window pixels per screen pixel, the overlay would hide the picture
+const MIN_OVERLAY_PIXEL: u32 = 3;
+
+pub type Line = ((i32, i32), (i32, i32));
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Viewport {
+
+    pub fn fit(window_width: u32, window_height: u32, scaling: Scaling) -> Self {
+        let (width, height) = match scaling {
+            // A window smaller than the screen itself falls back to fitting it
+            Scaling::Integer if window_width >= 64 && window_height >= 32 => {
+                let scale = (window_width / 64).min(window_height / 32);
+                (64 * scale, 32 * scale)
+            },
+            _ => {
+                let width = window_width.min(window_height * 2).max(2);
+                (width, width / 2)
+            }
+        };
+
+        Viewport {
+            x: (window_width as i32 - width as i32) / 2,
+            y: (window_height as i32 - height as i32) / 2,
+            width,
+            height
+        }
+    }
+
+    pub fn pixel_size(&self) -> f32 {
+        self.width as f32 / 64.0
+    }
+
+    // Lines to draw over the screen, in window coordinates
+    pub fn overlay_lines(&self, overlay: Overlay) -> Vec<Line> {
+        if self.width / 64 < MIN_OVERLAY_PIXEL {
+            return vec![];
+        }
+        let col_x = |col: u32| self.x + (col * self.width / 64) as i32;
+        let row_y = |row: u32| self.y + (row * self.height / 32) as i32;
+        let (right, bottom) = (col_x(64) - 1, row_y(32) - 1);
+
+        match overlay {
+            Overlay::None => vec![],
+            Overlay::Grid => {
+                let columns = (1..64).map(|col| ((col_x(col), self.y), (col_x(col), bottom)));
+                let rows = (1..32).map(|row| ((self.x, row_y(row)), (right, row_y(row))));
+                columns.chain(rows).collect()
+            },
+            Overlay::Scanlines => (1..=32)
+                .map(|row| ((self.x, row_y(row) - 1), (right, row_y(row) - 1)))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod viewport_test {
+    use super::{Overlay, Scaling, Viewport};
+
+    #[test]
+    fn integer_scaling_letterboxes() {
+        let view = Viewport::fit(700, 400, Scaling::Integer);
+        assert_eq!(view, Viewport { x: 30, y: 40, width: 640, height: 320 });
+        assert_eq!(view.pixel_size(), 10.0);
+
+        // Too small for a whole multiple
+        let view = Viewport::fit(50, 50, Scaling::Integer);
+        assert_eq!((view.width, view.height), (50, 25));
+    }
+
+    #[test]
+    fn fit_keeps_aspect_ratio() {
+        assert_eq!(Viewport::fit(700, 400, Scaling::Fit), Viewport { x: 0, y: 25, width: 700, height: 350 });
+        assert_eq!(Viewport::fit(1000, 300, Scaling::Fit), Viewport { x: 200, y: 0, width: 600, height: 300 });
+    }
+
+    #[test]
+    fn overlay_lines() {
+        let view = Viewport::fit(640, 320, Scaling::Integer);
+        assert_eq!(view.overlay_lines(Overlay::None).len(), 0);
+        assert_eq!(view.overlay_lines(Overlay::Grid).len(), 63 + 31);
+
+        let scanlines = view.overlay_lines(Overlay::Scanlines);
+        assert_eq!(scanlines.len(), 32);
+        assert_eq!(scanlines[0], ((0, 9), (639, 9)));
+
+        // Pixels too small to show either
+        let view = Viewport::fit(128, 64, Scaling::Integer);
+        assert_eq!(view.overlay_lines(Overlay::Grid).len(), 0);
+    }
+}
@@ -1,13 +1,32 @@
 use std::sync::mpsc::{Sender, Receiver};
-use super::chip8::{Chip8, keypad};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::romwatch::RomWatcher;
+use chip8::{Chip8, frontend::InputEvent, keypad, postprocess::{self, Filter, PostProcess}, quirks::Quirks, timing::{Speed, Timing}, capture::{self, Recorder}, palette::Palette, config::Config, viewport::{Overlay, Scaling, Viewport}, romdb::{self, RomDb, RomInfo}, rom::{self, RomLoader}, callstack::CallFrame, stack::{StackStorage, DEFAULT_STACK_DEPTH, MAX_STACK_DEPTH}, font::{Font, FontSet, DEFAULT_FONT_BASE, MAX_FONT_BASE}, symbols::Symbols};
+
+const OVERLAY_ALPHA: u8 = 96;
+// Files are often written in more than one go, so a reload waits for them to settle
+const RELOAD_DELAY: Duration = Duration::from_millis(150);
+const SPEEDS: [Speed; 7] = [Speed::Slow(4), Speed::Slow(2), Speed::Normal, Speed::Fast(2), Speed::Fast(4), Speed::Fast(8), Speed::Uncapped];
 
 pub struct DebugUI {
     tx: Sender<Command>,
-    rx_framebuffer: Receiver<[[u8; 64]; 32]>,
-    rx_status: Receiver<Status>,
-    rx_keyboard: Receiver<[u8; 16]>,
+    rx: Receiver<Update>,
 
     framebuffer: [[u8; 64]; 32],
+    post: PostProcess,
+    loaded_file: Option<String>,
+    romdb: RomDb,
+    rom_info: Option<RomInfo>,
+    rom_hash: String,
+    rom_error: String,
+    // Labels from the .sym file next to the ROM
+    symbols: Option<Symbols>,
+    run_to: String,
+    run_frames: u32,
+    hot_reload: bool,
+    watcher: Option<RomWatcher>,
+    reload_at: Option<Instant>,
     texture: Option<egui::TextureHandle>,
     debug: bool,
     executed: bool,
@@ -22,48 +41,132 @@ pub struct DebugUI {
     end_addr: u16,
 
     pressed: std::collections::HashSet<egui::Key>,
+    // Keypad keys held with the mouse, or toggled on in sticky mode, one bit per key
+    clicked_keys: u16,
+    sticky_keys: bool,
+    keymap: [(u8, egui::Key); 16],
+    rebinding: Option<u8>,
+    show_keymap_window: bool,
+    window_size: egui::Vec2,
+    window_position: Option<egui::Pos2>,
 
     color_on: ([f32; 3], egui::Color32),
     color_off: ([f32; 3], egui::Color32),
+    config: Config,
+    palette: Palette,
+    new_palette_name: String,
+    scaling: Scaling,
+    overlay: Overlay,
     picked_file: Option<String>,
-    frequency: u32,
+    cycles_per_frame: u32,
+    // What the user picked, the ROM database only overrides it for the ROM it matches
+    user_cycles_per_frame: u32,
+    user_quirks: Quirks,
+    // Picked from the list, and what the emulator runs at while no speed key is held
+    speed: Speed,
+    running_speed: Speed,
     rom_loaded: bool,
     continuous: bool,
+    quirks: Quirks,
+    timing: Timing,
+    stack_depth: usize,
+    isolated_stack: bool,
+    font: Font,
+    font_name: String,
+    font_base: u16,
+    font_error: String,
+
+    capture_scale: u32,
+    recorder: Option<Recorder>,
+    capture_message: String,
 
     keyboard: [u8; 16]
 }
 
 impl DebugUI {
-    pub fn new(tx: Sender<Command>, rx_framebuffer: Receiver<[[u8; 64]; 32]>, rx_status: Receiver<Status>, rx_keyboard: Receiver<[u8; 16]>) -> Self {
+    pub fn new(tx: Sender<Command>, rx: Receiver<Update>, config: Config) -> Self {
+        let palette = config.palette_for(None, None);
+        let settings = config.debugger.clone();
+
+        // Bring the emulator thread in line with the restored settings
+        _ = tx.send(Command::SetCyclesPerFrame(settings.cycles_per_frame as usize));
+        _ = tx.send(Command::Continuous(settings.continuous));
+        _ = tx.send(Command::Snapshot(settings.memory_start, settings.memory_end));
+
+        let mut keymap = KEYMAP;
+        for ((_, key), name) in keymap.iter_mut().zip(settings.keymap.iter()) {
+            if let Some(saved) = egui::Key::from_name(name) {
+                *key = saved;
+            }
+        }
+
         DebugUI {
-            tx: tx,
-            rx_framebuffer: rx_framebuffer,
-            rx_status: rx_status,
+            tx,
+            rx,
 
             framebuffer: [[0u8; 64]; 32],
+            post: PostProcess::new(Filter::None),
+            loaded_file: None,
+            romdb: RomDb::load(),
+            rom_info: None,
+            rom_hash: String::new(),
+            rom_error: String::new(),
+            symbols: None,
+            run_to: "0x0200".to_owned(),
+            run_frames: 1,
+            hot_reload: settings.hot_reload,
+            watcher: None,
+            reload_at: None,
             texture: None,
-            debug: false,
+            debug: settings.debug,
             executed: false,
 
             running: true,
             paused: true,
 
             status: Status::empty(),
-            show_memory_window: false,
+            show_memory_window: settings.show_memory_window,
 
-            start_addr: 0x0200,
-            end_addr: 0x020F,
+            start_addr: settings.memory_start,
+            end_addr: settings.memory_end,
 
             pressed: std::collections::HashSet::<egui::Key>::new(),
+            clicked_keys: 0,
+            sticky_keys: false,
+            keymap,
+            rebinding: None,
+            show_keymap_window: false,
+            window_size: egui::Vec2::from(settings.window_size),
+            window_position: settings.window_position.map(egui::Pos2::from),
 
             picked_file: None,
-            color_on: ([1.0; 3], egui::Color32::WHITE),
-            color_off: ([0.0; 3], egui::Color32::BLACK),
-            frequency: 540,
+            color_on: DebugUI::palette_color(palette.on()),
+            color_off: DebugUI::palette_color(palette.off()),
+            config,
+            palette,
+            new_palette_name: String::new(),
+            scaling: Scaling::Integer,
+            overlay: Overlay::None,
+            cycles_per_frame: settings.cycles_per_frame,
+            user_cycles_per_frame: settings.cycles_per_frame,
+            user_quirks: Quirks::new(),
+            speed: Speed::Normal,
+            running_speed: Speed::Normal,
             rom_loaded: false,
-            continuous: false,
+            continuous: settings.continuous,
+            quirks: Quirks::new(),
+            timing: Timing::Fixed,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            isolated_stack: false,
+            font: Font::default(),
+            font_name: FontSet::Schip.name().to_owned(),
+            font_base: DEFAULT_FONT_BASE,
+            font_error: String::new(),
+
+            capture_scale: 10,
+            recorder: None,
+            capture_message: String::new(),
 
-            rx_keyboard: rx_keyboard,
             keyboard: keypad::DEFAULT_LAYOUT
         }
     }
@@ -73,15 +176,11 @@ impl DebugUI {
             [64, 32],
             vec![egui::Color32::BLACK; 64 * 32]
         );
-        for y in 0..32 {
-            for x in 0..64 {
-                let color = if self.framebuffer[y][x] == 1 {
-                    self.color_on.1
-                } else {
-                    self.color_off.1
-                };
-                image.pixels[y * 64 + x] = color;
-            }
+        let (on, off) = (self.color_on.1, self.color_off.1);
+        let intensities = self.post.output();
+        for (pixel, intensity) in image.pixels.iter_mut().zip(intensities.iter().flatten()) {
+            let [r, g, b] = postprocess::mix([off.r(), off.g(), off.b()], [on.r(), on.g(), on.b()], *intensity);
+            *pixel = egui::Color32::from_rgb(r, g, b);
         }
         if let Some(tex) = &mut self.texture {
             tex.set(image, egui::TextureOptions::NEAREST)
@@ -90,21 +189,549 @@ impl DebugUI {
         }
     }
 
+    fn rom_info_view(&self, ui: &mut egui::Ui) {
+        match &self.rom_info {
+            Some(info) => {
+                ui.strong(&info.title);
+                let details: Vec<&str> = [&info.author, &info.platform].into_iter().flatten().map(|s| s.as_str()).collect();
+                if !details.is_empty() {
+                    ui.label(details.join(" - "));
+                }
+                for hint in info.key_hints() {
+                    ui.label(hint);
+                }
+            },
+            None => { ui.label("Not in the ROM database"); }
+        }
+        ui.monospace(format!("SHA-1: {}", self.rom_hash));
+        if let Some(symbols) = &self.symbols {
+            ui.label(format!("{} labels loaded", symbols.len()));
+        }
+        ui.add_space(10.0);
+    }
+
+    fn save_settings(&mut self) {
+        let settings = &mut self.config.debugger;
+        settings.cycles_per_frame = self.user_cycles_per_frame;
+        settings.window_size = self.window_size.into();
+        settings.window_position = self.window_position.map(|position| position.into());
+        settings.debug = self.debug;
+        settings.show_memory_window = self.show_memory_window;
+        settings.memory_start = self.start_addr;
+        settings.memory_end = self.end_addr;
+        settings.continuous = self.continuous;
+        settings.hot_reload = self.hot_reload;
+        settings.keymap = self.keymap.iter().map(|(_, key)| key.name().to_owned()).collect();
+
+        if let Err(e) = self.config.save() {
+            eprintln!("{}", e);
+        }
+    }
+
+    fn load_rom(&mut self, ctx: &egui::Context, path: String) {
+        let rom = match RomLoader::new().load_file(&path) {
+            Ok(rom) => rom.data,
+            Err(e) => {
+                self.rom_error = e.to_string();
+                if let rom::RomError::Read(_) = e {
+                    self.config.debugger.recent_roms.retain(|recent| *recent != path);
+                }
+                return;
+            }
+        };
+        self.rom_error.clear();
+        self.symbols = match Symbols::load_for(Path::new(&path)) {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                self.rom_error = e;
+                None
+            }
+        };
+        self.rom_hash = romdb::sha1_hex(&rom);
+        self.rom_info = self.romdb.lookup(&rom).cloned();
+        _ = self.tx.send(Command::LoadRom(rom));
+
+        // Start over from the user's settings, so that the previous ROM's don't carry over
+        self.quirks = self.user_quirks;
+        self.cycles_per_frame = self.user_cycles_per_frame;
+        if let Some(info) = &self.rom_info {
+            info.apply_quirks(&mut self.quirks);
+            if let Some(cycles) = info.cycles_per_frame {
+                self.cycles_per_frame = cycles;
+            }
+        }
+        _ = self.tx.send(Command::SetQuirks(self.quirks));
+        _ = self.tx.send(Command::SetCyclesPerFrame(self.cycles_per_frame as usize));
+        let suggested = self.rom_info.as_ref().and_then(|info| info.palette.clone());
+
+        self.config.debugger.add_recent_rom(&path);
+        self.post.filter = self.config.filter_for(Path::new(&path));
+        self.set_palette(self.config.palette_for(Some(Path::new(&path)), suggested.as_deref()));
+        self.post.reset();
+        self.loaded_file = Some(path);
+        self.paused = true;
+        self.rom_loaded = true;
+        // Loading resets the keypad
+        self.clicked_keys = 0;
+        self.framebuffer = [[0u8; 64]; 32];
+        self.update_texture(ctx);
+        self.watch_loaded_file();
+    }
+
+    fn watch_loaded_file(&mut self) {
+        self.reload_at = None;
+        self.watcher = match (&self.loaded_file, self.hot_reload) {
+            (Some(path), true) => match RomWatcher::new(Path::new(path)) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    self.rom_error = format!("Could not watch {}: {}", path, e);
+                    None
+                }
+            },
+            _ => None
+        };
+    }
+
+    // Loads the ROM again once it changed on disk, carrying on if it was running
+    fn check_hot_reload(&mut self, ctx: &egui::Context) {
+        let Some(watcher) = &self.watcher else { return };
+        if watcher.changed() {
+            self.reload_at = Some(Instant::now() + RELOAD_DELAY);
+        }
+        ctx.request_repaint_after(RELOAD_DELAY);
+
+        if let (Some(at), Some(path)) = (self.reload_at, self.loaded_file.clone()) && Instant::now() >= at {
+            let running = !self.paused;
+            self.load_rom(ctx, path);
+            if running && self.rom_error.is_empty() {
+                _ = self.tx.send(Command::Resume);
+                self.paused = false;
+            }
+        }
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|input| input.raw.dropped_files.iter().find_map(|file| file.path.clone()));
+        if let Some(path) = dropped {
+            self.picked_file = None;
+            self.load_rom(ctx, path.display().to_string());
+        }
+    }
+
+    fn exit(&mut self) {
+        self.save_settings();
+        _ = self.tx.send(Command::Input(InputEvent::Quit));
+        self.running = false;
+        std::thread::sleep(
+            std::time::Duration::from_millis(
+                400
+            )
+        );
+        std::process::exit(0);
+    }
+
+    // Binds the next key pressed to the keypad key being changed; Escape cancels
+    fn handle_rebinding(&mut self, ctx: &egui::Context) {
+        let Some(chip8_key) = self.rebinding else { return };
+        let pressed = ctx.input(|input| input.events.iter().find_map(|event| match event {
+            egui::Event::Key { key, pressed: true, .. } => Some(*key),
+            _ => None
+        }));
+        let Some(pressed) = pressed else { return };
+
+        if pressed != egui::Key::Escape {
+            let previous = self.keymap[chip8_key as usize].1;
+            // A key already in use swaps places with the one being changed
+            for (_, key) in self.keymap.iter_mut().filter(|(_, key)| *key == pressed) {
+                *key = previous;
+            }
+            self.keymap[chip8_key as usize].1 = pressed;
+        }
+        self.rebinding = None;
+    }
+
+    fn keymap_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_keymap_window;
+        egui::Window::new("Keymap").open(&mut open).show(ctx, |ui| {
+            egui::Grid::new("keymap").show(ui, |ui| {
+                for row in 0..4 {
+                    for col in 0..4 {
+                        let (chip8_key, key) = self.keymap[row * 4 + col];
+                        let text = if self.rebinding == Some(chip8_key) {
+                            format!("{:1X}: ...", chip8_key)
+                        } else {
+                            format!("{:1X}: {}", chip8_key, key.name())
+                        };
+                        if ui.button(text).clicked() {
+                            self.rebinding = Some(chip8_key);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.label("Click a key, then press the keyboard key to use for it");
+            if ui.button("Defaults").clicked() {
+                self.keymap = KEYMAP;
+                self.rebinding = None;
+            }
+        });
+        self.show_keymap_window = open;
+    }
+
     fn handle_input(&mut self, ctx: &egui::Context) {
+        if self.rebinding.is_some() { return }
         ctx.input(|input| {
-            for(chip8_key, egui_key) in KEYMAP {
+            for(chip8_key, egui_key) in self.keymap {
                 let was_pressed = self.pressed.contains(&egui_key);
                 let is_down = input.key_down(egui_key);
                 if is_down && !was_pressed {
                     self.pressed.insert(egui_key);
-                    _ = self.tx.send(Command::KeyDown(chip8_key));
+                    _ = self.tx.send(Command::Input(InputEvent::KeyDown(chip8_key)));
                 } else if !is_down && was_pressed {
                     self.pressed.remove(&egui_key);
-                    _ = self.tx.send(Command::KeyUp(chip8_key));
+                    _ = self.tx.send(Command::Input(InputEvent::KeyUp(chip8_key)));
                 }
             }
         });
     }
+    // Runs until an address, given in hex or as a label, or for a number of frames, then pauses
+    fn run_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let address = self.parse_address(&self.run_to);
+            if ui.add_enabled(address.is_some(), egui::Button::new("Run to")).clicked() && let Some(address) = address {
+                _ = self.tx.send(Command::RunToAddress(address));
+                self.paused = false;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.run_to).desired_width(90.0));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Run").clicked() {
+                _ = self.tx.send(Command::RunFrames(self.run_frames));
+                self.paused = false;
+            }
+            ui.add(egui::DragValue::new(&mut self.run_frames).range(1..=3600));
+            ui.label(if self.run_frames == 1 { "frame" } else { "frames" });
+        });
+    }
+
+    fn parse_address(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        let from_label = self.symbols.as_ref().and_then(|symbols| symbols.address_of(text));
+        from_label.or_else(|| u16::from_str_radix(text.trim_start_matches("0x"), 16).ok().filter(|address| *address < 0x1000))
+    }
+
+    fn describe(&self, address: u16) -> String {
+        match &self.symbols {
+            Some(symbols) => symbols.describe(address),
+            None => format!("{:#06x}", address)
+        }
+    }
+
+    // Innermost call first, with a warning when the next CALLs would overflow the stack
+    fn call_stack_view(&self, ui: &mut egui::Ui) {
+        let depth = self.status.call_stack.len();
+        let capacity = self.status.stack_capacity;
+        ui.horizontal(|ui| {
+            ui.label("Call stack");
+            let text = format!("Depth: {}/{}", depth, capacity);
+            if depth >= capacity {
+                ui.colored_label(egui::Color32::LIGHT_RED, text + " - full, the next CALL overflows");
+            } else if depth + 2 >= capacity {
+                ui.colored_label(egui::Color32::ORANGE, text + " - nearly full");
+            } else {
+                ui.label(text);
+            }
+        });
+        if let Some(fault) = &self.status.fault {
+            ui.colored_label(egui::Color32::LIGHT_RED, format!("Stopped: {}", fault));
+        }
+        ui.group(|ui| {
+            if depth == 0 {
+                ui.label("Not in a subroutine");
+            }
+            for (level, frame) in self.status.call_stack.iter().enumerate().rev() {
+                ui.monospace(format!("#{} CALL {} at {}", level, self.describe(frame.target), self.describe(frame.call_site)));
+                ui.monospace(format!("   returns to {}", self.describe(frame.return_address)));
+                if level > 0 {
+                    ui.separator();
+                }
+            }
+        });
+    }
+
+    // The keypad as buttons, lit while the emulator sees the key down. A button is held while
+    // the mouse button is, or in sticky mode toggled by each click, to keep a key down while stepping
+    fn virtual_keypad(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("keypad-status").spacing(egui::vec2(4.0, 4.0)).show(ui, |ui| {
+            for cell in 0..16 {
+                let key = keypad::key_at(cell);
+                let bit = 1 << key;
+                let text = egui::RichText::new(format!("{:1X}", key)).monospace().size(18.0);
+                let button = egui::Button::new(text)
+                    .min_size(egui::vec2(36.0, 36.0))
+                    .sense(egui::Sense::click_and_drag())
+                    .selected(keypad::is_held(&self.keyboard, key));
+                let response = ui.add(button);
+
+                let held = self.clicked_keys & bit != 0;
+                let down = if self.sticky_keys {
+                    if response.clicked() { !held } else { held }
+                } else {
+                    response.is_pointer_button_down_on()
+                };
+                if down != held {
+                    self.clicked_keys ^= bit;
+                    _ = self.tx.send(Command::Input(if down { InputEvent::KeyDown(key) } else { InputEvent::KeyUp(key) }));
+                }
+                if cell % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+        if ui.checkbox(&mut self.sticky_keys, "Sticky keys").changed() && !self.sticky_keys {
+            self.release_clicked_keys();
+        }
+    }
+
+    fn release_clicked_keys(&mut self) {
+        for key in 0..16 {
+            if self.clicked_keys & (1 << key) != 0 {
+                _ = self.tx.send(Command::Input(InputEvent::KeyUp(key)));
+            }
+        }
+        self.clicked_keys = 0;
+    }
+
+    // Held down, Tab fast-forwards (with Shift as fast as possible) and ` slows down
+    fn handle_speed_keys(&mut self, ctx: &egui::Context) {
+        if self.rebinding.is_some() { return }
+        let speed = ctx.input(|input| {
+            if input.key_down(egui::Key::Tab) {
+                if input.modifiers.shift { Speed::Uncapped } else { Speed::FAST_FORWARD }
+            } else if input.key_down(egui::Key::Backtick) {
+                Speed::SLOW_MOTION
+            } else {
+                self.speed
+            }
+        });
+        if speed != self.running_speed {
+            self.running_speed = speed;
+            _ = self.tx.send(Command::Input(InputEvent::SetSpeed(speed)));
+        }
+    }
+
+    fn speed_controls(&mut self, ui: &mut egui::Ui) {
+        // Each instruction takes its own time with VIP timing, so the number per frame does not apply
+        let c = self.cycles_per_frame;
+        let slider = egui::Slider::new(&mut self.cycles_per_frame, 1..=1000)
+            .logarithmic(true)
+            .text(format!("Cycles per frame ({} Hz)", c * 60));
+        if ui.add_enabled(self.timing == Timing::Fixed, slider).changed() {
+            self.user_cycles_per_frame = self.cycles_per_frame;
+            _ = self.tx.send(Command::SetCyclesPerFrame(self.cycles_per_frame as usize));
+        }
+        ui.add_space(10.0);
+        ui.label("Speed: ");
+        egui::ComboBox::from_id_salt("speed")
+            .selected_text(self.running_speed.label())
+            .show_ui(ui, |ui| {
+                for speed in SPEEDS {
+                    ui.selectable_value(&mut self.speed, speed, speed.label());
+                }
+            });
+    }
+
+    // A built-in font or one read from a file, and the address it is loaded at
+    fn font_picker(&mut self, ui: &mut egui::Ui) {
+        let mut picked = None;
+        let mut error = None;
+        ui.label("Font: ");
+        egui::ComboBox::from_id_salt("font")
+            .selected_text(&self.font_name)
+            .show_ui(ui, |ui| {
+                for set in FontSet::ALL {
+                    if ui.selectable_label(self.font_name == set.name(), set.name()).clicked() {
+                        picked = Some((set.font(), set.name().to_owned()));
+                    }
+                }
+                if ui.selectable_label(false, "From file...").clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_file() {
+                    match Font::from_file(&path) {
+                        Ok(font) => picked = Some((font, path.file_name().unwrap_or_default().to_string_lossy().into_owned())),
+                        Err(e) => error = Some(e)
+                    }
+                }
+            });
+        ui.label("at");
+        let moved = ui.add(egui::DragValue::new(&mut self.font_base).range(0..=MAX_FONT_BASE).hexadecimal(3, false, false).prefix("0x")).changed();
+
+        if let Some(e) = error {
+            self.font_error = e;
+        }
+        if !self.font_error.is_empty() {
+            ui.colored_label(egui::Color32::LIGHT_RED, &self.font_error);
+        }
+        let changed = picked.is_some();
+        if let Some((font, name)) = picked {
+            self.font = font;
+            self.font_name = name;
+            self.font_error.clear();
+        }
+        if changed || moved {
+            _ = self.tx.send(Command::SetFont(Box::new(self.font), self.font_base));
+        }
+    }
+
+    // Returns true if the filter changed
+    fn filter_picker(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut filter = self.post.filter;
+        ui.label("Filter: ");
+        egui::ComboBox::from_id_salt("filter")
+            .selected_text(filter.name())
+            .show_ui(ui, |ui| {
+                for option in Filter::ALL {
+                    if ui.selectable_label(filter.name() == option.name(), option.name()).clicked() {
+                        filter = option;
+                    }
+                }
+            });
+        if let Filter::Phosphor(persistence) = &mut filter {
+            ui.add(egui::Slider::new(persistence, 0.1..=0.95).text("Persistence"));
+        }
+
+        if filter == self.post.filter { return false }
+        self.post.filter = filter;
+        // Saved with the other settings on exit, not on every move of the slider
+        if let Some(file) = &self.loaded_file {
+            self.config.remember_filter(Path::new(file), filter);
+        }
+        true
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.color_on = DebugUI::palette_color(palette.on());
+        self.color_off = DebugUI::palette_color(palette.off());
+        self.palette = palette;
+    }
+
+    fn remember_palette(&mut self) {
+        let rom = self.loaded_file.as_deref().map(Path::new);
+        self.config.remember_palette(rom, &self.palette.name);
+        if let Err(e) = self.config.save() {
+            self.capture_message = e;
+        }
+    }
+
+    fn palette_picker(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut picked = None;
+        ui.label("Palette: ");
+        egui::ComboBox::from_id_salt("palette")
+            .selected_text(&self.palette.name)
+            .show_ui(ui, |ui| {
+                for palette in self.config.all_palettes() {
+                    if ui.selectable_label(palette == self.palette, &palette.name).clicked() {
+                        picked = Some(palette);
+                    }
+                }
+            });
+
+        // The colours edited by hand can be kept as a new palette
+        ui.add(egui::TextEdit::singleline(&mut self.new_palette_name).hint_text("name").desired_width(80.0));
+        if ui.button("Save palette").clicked() && !self.new_palette_name.trim().is_empty() {
+            let mut colors = self.palette.colors;
+            colors[0] = [self.color_off.1.r(), self.color_off.1.g(), self.color_off.1.b()];
+            colors[1] = [self.color_on.1.r(), self.color_on.1.g(), self.color_on.1.b()];
+            let palette = Palette::new(self.new_palette_name.trim(), colors);
+            self.config.add_palette(palette.clone());
+            self.new_palette_name.clear();
+            picked = Some(palette);
+        }
+
+        let Some(palette) = picked else { return false };
+        self.set_palette(palette);
+        self.remember_palette();
+        true
+    }
+
+    fn view_controls(&mut self, ui: &mut egui::Ui) {
+        ui.label("Scaling: ");
+        egui::ComboBox::from_id_salt("scaling")
+            .selected_text(self.scaling.name())
+            .show_ui(ui, |ui| {
+                for option in Scaling::ALL {
+                    ui.selectable_value(&mut self.scaling, option, option.name());
+                }
+            });
+        ui.add_space(10.0);
+        ui.label("Overlay: ");
+        egui::ComboBox::from_id_salt("overlay")
+            .selected_text(self.overlay.name())
+            .show_ui(ui, |ui| {
+                for option in Overlay::ALL {
+                    ui.selectable_value(&mut self.overlay, option, option.name());
+                }
+            });
+        ui.add_space(10.0);
+        if ui.button("Fullscreen (F11)").clicked() {
+            DebugUI::toggle_fullscreen(ui.ctx());
+        }
+    }
+
+    fn toggle_fullscreen(ctx: &egui::Context) {
+        let fullscreen = ctx.input(|input| input.viewport().fullscreen.unwrap_or(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!fullscreen));
+    }
+
+    fn capture_controls(&mut self, ui: &mut egui::Ui) {
+        let on = [self.color_on.1.r(), self.color_on.1.g(), self.color_on.1.b()];
+        let off = [self.color_off.1.r(), self.color_off.1.g(), self.color_off.1.b()];
+
+        ui.label("Capture scale: ");
+        ui.add(egui::DragValue::new(&mut self.capture_scale).range(1..=20));
+        ui.add_space(10.0);
+
+        if ui.button("Screenshot").clicked() {
+            let picked = rfd::FileDialog::new()
+                .add_filter("PNG", &["png"])
+                .set_file_name(capture::timestamped_name("screenshot", "png"))
+                .save_file();
+            if let Some(path) = picked {
+                self.capture_message = match capture::save_screenshot(&self.framebuffer, self.capture_scale, on, off, &path) {
+                    Ok(_) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Could not save screenshot: {}", e)
+                };
+            }
+        }
+
+        match self.recorder.take() {
+            None => if ui.button("Record").clicked() {
+                self.recorder = Some(Recorder::new(self.capture_scale, on, off));
+                self.capture_message = String::new();
+            },
+            Some(recorder) => {
+                if ui.button(format!("Stop recording ({} frames)", recorder.frame_count())).clicked() {
+                    let picked = rfd::FileDialog::new()
+                        .add_filter("GIF", &["gif"])
+                        .set_file_name(capture::timestamped_name("recording", "gif"))
+                        .save_file();
+                    if let Some(path) = picked {
+                        self.capture_message = match recorder.finish(&path) {
+                            Ok(_) => format!("Saved {}", path.display()),
+                            Err(e) => format!("Could not save recording: {}", e)
+                        };
+                    }
+                } else {
+                    self.recorder = Some(recorder);
+                }
+            }
+        }
+        ui.label(&self.capture_message);
+    }
+
+    fn palette_color([r, g, b]: [u8; 3]) -> ([f32; 3], egui::Color32) {
+        ([r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0], egui::Color32::from_rgb(r, g, b))
+    }
+
     fn rgb_to_color(rgb: [f32; 3]) -> egui::Color32 {
         egui::Color32::from_rgb(
             (rgb[0] * 255.0).round() as u8,
@@ -143,24 +770,62 @@ const KEYMAP: [(u8, egui::Key); 16] =
 impl eframe::App for DebugUI {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
 
-        match self.rx_keyboard.try_recv() {
-            Ok(keys) => self.keyboard = keys,
-            Err(_) => {} // no change
-        }
-        
+        self.handle_rebinding(ctx);
+        self.handle_dropped_files(ctx);
+        self.check_hot_reload(ctx);
         self.handle_input(ctx);
-        match self.rx_framebuffer.try_recv() {
-            Ok(fb) => {
-                self.framebuffer = fb;
-                self.update_texture(ctx);
-            },
-            Err(_) => { /* no change */ }
+        self.handle_speed_keys(ctx);
+        if ctx.input(|input| input.key_pressed(egui::Key::F11)) {
+            DebugUI::toggle_fullscreen(ctx);
+        }
+
+        // Only the windowed layout is kept, a fullscreen one is not restored
+        let (inner, outer, fullscreen) = ctx.input(|input| {
+            let viewport = input.viewport();
+            (viewport.inner_rect, viewport.outer_rect, viewport.fullscreen.unwrap_or(false))
+        });
+        if !fullscreen {
+            if let Some(inner) = inner { self.window_size = inner.size() }
+            if let Some(outer) = outer { self.window_position = Some(outer.min) }
+        }
+        if ctx.input(|input| input.viewport().close_requested()) {
+            self.save_settings();
+        }
+        let mut new_frame = false;
+        for update in self.rx.try_iter() {
+            match update {
+                Update::Frame(fb) => {
+                    self.framebuffer = *fb;
+                    self.post.process(&self.framebuffer);
+                    if let Some(recorder) = &mut self.recorder {
+                        recorder.push(&self.framebuffer);
+                    }
+                    new_frame = true;
+                }
+                // Also tells when a step over or step out is done
+                Update::Status(status) => {
+                    if let Some(error) = &status.load_error {
+                        self.rom_error = error.clone();
+                        self.rom_loaded = false;
+                        self.loaded_file = None;
+                        self.watcher = None;
+                        self.reload_at = None;
+                    }
+                    self.paused = status.paused;
+                    self.status = status;
+                }
+                Update::Keyboard(keys) => self.keyboard = keys
+            }
+        }
+        if new_frame {
+            self.update_texture(ctx);
         }
 
         egui::SidePanel::left("debug").show(ctx, |ui| {
             ui.heading("Debug info");
             
             if self.rom_loaded {
+                self.rom_info_view(ui);
                 ui.label(format!("Emulator is{}running", 
                     match self.running {
                         true => " ",
@@ -224,6 +889,15 @@ impl eframe::App for DebugUI {
                                 self.handle_input(ctx);
                             _ = self.tx.send(Command::Step);
                         }
+                        // Both may keep running for a while, the status says when they are done
+                        if ui.button("Step over").clicked() {
+                            _ = self.tx.send(Command::StepOver);
+                            self.paused = false;
+                        }
+                        if ui.add_enabled(!self.status.call_stack.is_empty(), egui::Button::new("Step out")).clicked() {
+                            _ = self.tx.send(Command::StepOut);
+                            self.paused = false;
+                        }
                         if ui.button("Run").clicked() {
                             _ = self.tx.send(Command::Resume);
                             self.paused = false;
@@ -231,35 +905,20 @@ impl eframe::App for DebugUI {
                     }
                     
                     if ui.button("Exit").clicked() {
-                        _ = self.tx.send(Command::Exit);
-                        self.running = false;
-                        std::thread::sleep(
-                            std::time::Duration::from_millis(
-                                400
-                            )
-                        );
-                        std::process::exit(0);
+                        self.exit();
                     }
                 });
+                if self.paused {
+                    self.run_controls(ui);
+                }
             } else { 
                 if ui.button("Exit").clicked() {
-                    _ = self.tx.send(Command::Exit);
-                    self.running = false;
-                    std::thread::sleep(
-                        std::time::Duration::from_millis(
-                            400
-                        )
-                    );
-                    std::process::exit(0);
+                    self.exit();
                 }
             }
             ui.add_space(15.0);
 
             if self.debug {
-                match self.rx_status.try_recv() {
-                    Ok(status) => self.status = status,
-                    Err(_) => {} // no change
-                }
                 ui.label("CPU registers");
                 ui.group(|ui| {
                     
@@ -286,26 +945,18 @@ impl eframe::App for DebugUI {
                     });
                 });
                 ui.add_space(15.0);
-                ui.label("Stack content");
-                let base: u8 = 0x0050;
-                ui.group(|ui| {
-                    for addr in (base..self.status.sp).step_by(2) {
-                        let value = (self.status.stack[(addr - base) as usize] as u16) << 8 
-                            |
-                            (self.status.stack[(addr - base + 1) as usize] as u16);
-                        ui.label(format!("{:#04x}: {:#06x}", addr, value));
-                        ui.separator();
+                self.call_stack_view(ui);
+                ui.add_space(25.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Memory view").clicked() {
+                        self.show_memory_window = !self.show_memory_window;
                     }
-                    if base <= self.status.sp {
-                        let value = (self.status.stack[(self.status.sp - base) as usize] as u16) << 8 
-                            |
-                            (self.status.stack[(self.status.sp - base + 1) as usize] as u16);
-                        ui.label(format!("{:#04x}: {:#06x}", self.status.sp, value));
+                    if ui.button("Keymap").clicked() {
+                        self.show_keymap_window = !self.show_keymap_window;
                     }
                 });
-                ui.add_space(25.0);
-                if ui.button("Memory view").clicked() {
-                    self.show_memory_window = !self.show_memory_window;
+                if self.show_keymap_window {
+                    self.keymap_window(ctx);
                 }
 
                 if self.show_memory_window {
@@ -359,64 +1010,10 @@ impl eframe::App for DebugUI {
             
             ui.add_space(15.0);
             ui.label("Keypad");
-            ui.group(|ui| {
-                egui::Grid::new("keypad-status").striped(true).show(ui, |ui| {
-                    let keypad = &self.keyboard;
-                    for i in 0..4 {
-                        ui.colored_label(
-                            if keypad[i * 4] & 0xF0 != 0 {
-                                egui::Color32::LIGHT_RED
-                            } else {
-                                egui::Color32::DARK_GRAY
-                            },
-                            format!("{:1x}", keypad[i * 4] & 0x0F)
-                        );
-
-                        ui.colored_label(
-                            if keypad[i * 4 + 1] & 0xF0 != 0 {
-                                egui::Color32::LIGHT_RED
-                            } else {
-                                egui::Color32::DARK_GRAY
-                            },
-                            format!("{:1x}", keypad[i * 4 + 1] & 0x0F)
-                        );
-
-                        ui.colored_label(
-                            if keypad[i * 4 + 2] & 0xF0 != 0 {
-                                egui::Color32::LIGHT_RED
-                            } else {
-                                egui::Color32::DARK_GRAY
-                            },
-                            format!("{:1x}", keypad[i * 4 + 2] & 0x0F)
-                        );
-
-                        ui.colored_label(
-                            if keypad[i * 4 + 3] & 0xF0 != 0 {
-                                egui::Color32::LIGHT_RED
-                            } else {
-                                egui::Color32::DARK_GRAY
-                            },
-                            format!("{:1x}", keypad[i * 4 + 3] & 0x0F)
-                        );
-                        ui.end_row();
-                    }
-                });
-            });
+            ui.group(|ui| self.virtual_keypad(ui));
         });
 
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(tex) = &self.texture {
-                ui.add(
-                    egui::Image::new(tex).max_size(egui::Vec2::new(640.0, 320.0)).fit_to_exact_size(egui::Vec2::new(640.0, 320.0))
-                );
-                ui.add_space(15.0);
-                let f = self.frequency;
-                if ui.add(egui::Slider::new(&mut self.frequency, 1..=600).text(format!("Frequency: {} Hz", f))).changed() {
-                    _ = self.tx.send(Command::ChangeFreq(self.frequency));
-                }
-            }
-        });
         egui::TopBottomPanel::bottom("Tweaks").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.label("ON color: ");
@@ -425,49 +1022,151 @@ impl eframe::App for DebugUI {
                 ui.label("OFF color: ");
                 if ui.color_edit_button_rgb(&mut self.color_off.0).changed() { self.color_off.1 = DebugUI::rgb_to_color(self.color_off.0); self.update_texture(ctx); }
                 ui.add_space(10.0);
+                if self.palette_picker(ui) {
+                    self.update_texture(ctx);
+                }
+                ui.add_space(10.0);
+                if self.filter_picker(ui) {
+                    self.post.process(&self.framebuffer);
+                    self.update_texture(ctx);
+                }
+                ui.add_space(10.0);
+                if ui.checkbox(&mut self.quirks.display_wait, "Display wait").changed() {
+                    self.user_quirks.display_wait = self.quirks.display_wait;
+                    _ = self.tx.send(Command::SetQuirks(self.quirks));
+                }
+                if ui.checkbox(&mut self.quirks.wait_for_release, "Wait for release").changed() {
+                    self.user_quirks.wait_for_release = self.quirks.wait_for_release;
+                    _ = self.tx.send(Command::SetQuirks(self.quirks));
+                }
+                let mut vip = self.timing == Timing::Vip;
+                if ui.checkbox(&mut vip, "VIP timing").changed() {
+                    self.timing = if vip { Timing::Vip } else { Timing::Fixed };
+                    _ = self.tx.send(Command::SetTiming(self.timing));
+                }
+                ui.add_space(10.0);
+                ui.label("Stack depth:");
+                let depth_changed = ui.add(egui::DragValue::new(&mut self.stack_depth).range(1..=MAX_STACK_DEPTH)).changed();
+                if ui.checkbox(&mut self.isolated_stack, "Isolated stack").changed() || depth_changed {
+                    let storage = if self.isolated_stack { StackStorage::Isolated } else { StackStorage::Memory };
+                    _ = self.tx.send(Command::SetStack(self.stack_depth, storage));
+                }
+                ui.add_space(10.0);
+                self.font_picker(ui);
+                ui.add_space(10.0);
                 if ui.button("Insert ROM: ").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    if let Some(path) = rfd::FileDialog::new().add_filter("CHIP-8 ROM", &rom::EXTENSIONS).pick_file() {
                         self.picked_file = Some(path.display().to_string());
                     }
                 }
+                let mut recent = None;
+                ui.add_enabled_ui(!self.config.debugger.recent_roms.is_empty(), |ui| {
+                    ui.menu_button("Recent ROMs", |ui| {
+                        for path in &self.config.debugger.recent_roms {
+                            if ui.button(path).clicked() {
+                                recent = Some(path.clone());
+                                ui.close();
+                            }
+                        }
+                    });
+                });
+                if let Some(path) = recent {
+                    self.picked_file = None;
+                    self.load_rom(ctx, path);
+                }
+                if self.rom_loaded && ui.checkbox(&mut self.hot_reload, "Reload on change").changed() {
+                    self.watch_loaded_file();
+                }
+                if !self.rom_error.is_empty() {
+                    ui.colored_label(egui::Color32::LIGHT_RED, &self.rom_error);
+                }
                 if let Some(picked_path) = &self.picked_file {
                     ui.monospace(picked_path);
                     
                     if ui.button("Load ROM").clicked() {
-                        if let Ok(rom) = std::fs::read(picked_path) {
-                            _ = self.tx.send(Command::LoadRom(rom));
-                        }
+                        let path = picked_path.clone();
                         self.picked_file = None;
-                        self.paused = true;
-                        self.rom_loaded = true;
-                        self.framebuffer = [[0u8; 64]; 32];
-                        self.update_texture(ctx);
+                        self.load_rom(ctx, path);
                     }
                 }
             });
+            ui.horizontal(|ui| {
+                self.view_controls(ui);
+                if self.rom_loaded {
+                    ui.add_space(10.0);
+                    self.capture_controls(ui);
+                }
+            });
+        });
+
+        // Added after the other panels, so that the screen gets the space they leave
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if ctx.input(|input| !input.raw.hovered_files.is_empty()) {
+                ui.heading("Drop the ROM to load it");
+            }
+            if let Some(tex) = &self.texture {
+                // Leave room for the speed controls under the screen
+                let size = (ui.available_size() - egui::vec2(0.0, 40.0)).max(egui::vec2(64.0, 32.0));
+                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                let view = Viewport::fit(rect.width() as u32, rect.height() as u32, self.scaling);
+                let to_pos = |(x, y): (i32, i32)| rect.min + egui::vec2(x as f32, y as f32);
+
+                ui.painter().rect_filled(rect, 0.0, egui::Color32::BLACK);
+                egui::Image::new(tex).paint_at(ui, egui::Rect::from_min_size(
+                    to_pos((view.x, view.y)),
+                    egui::vec2(view.width as f32, view.height as f32)
+                ));
+                // Half a point in, so that one point wide lines cover whole pixels
+                let stroke = egui::Stroke::new(1.0, egui::Color32::from_black_alpha(OVERLAY_ALPHA));
+                for (from, to) in view.overlay_lines(self.overlay) {
+                    let half = egui::vec2(0.5, 0.5);
+                    ui.painter().line_segment([to_pos(from) + half, to_pos(to) + half], stroke);
+                }
+
+                ui.add_space(15.0);
+                ui.horizontal(|ui| self.speed_controls(ui));
+            }
         });
     }
 }
 
 pub enum Command {
+    // Keys, speed and quitting, as from any other frontend
+    Input(InputEvent),
+
     Pause,
     Resume,
-    Exit,
 
     Snapshot(u16, u16),
 
     Fetch,
     Execute,
     Step,
-
-    KeyDown(u8),
-    KeyUp(u8),
+    StepOver,
+    StepOut,
+    RunToAddress(u16),
+    RunFrames(u32),
 
     LoadRom(Vec<u8>),
 
-    ChangeFreq(u32),
+    SetCyclesPerFrame(usize),
+
+    Continuous(bool),
 
-    Continuous(bool)
+    SetQuirks(Quirks),
+
+    SetTiming(Timing),
+
+    SetStack(usize, StackStorage),
+
+    SetFont(Box<Font>, u16)
+}
+// What the emulator thread sends back
+pub enum Update {
+    Frame(Box<[[u8; 64]; 32]>),
+    Status(Status),
+    // As Keypad::keys
+    Keyboard([u8; 16])
 }
 pub struct Status {
     pc: u16,
@@ -478,21 +1177,24 @@ pub struct Status {
     st: u8,
 
     v: [u8; 16],
-    stack: [u8; 32],
+    call_stack: Vec<CallFrame>,
+    stack_capacity: usize,
+    fault: Option<String>,
+    paused: bool,
 
     mem_view: Vec<u8>,
 
     opcode: u16,
-    mnemonic: String
+    mnemonic: String,
+    // Why the last ROM sent could not be loaded
+    load_error: Option<String>
 }
 impl Status {
     pub fn empty() -> Self {
-        Status{pc: 0, sp: 0, i: 0, dt: 0, st: 0, v: [0; 16], stack: [0; 32], mem_view: vec![], opcode: 0, mnemonic: String::new()}
+        Status{pc: 0, sp: 0, i: 0, dt: 0, st: 0, v: [0; 16], call_stack: vec![], stack_capacity: DEFAULT_STACK_DEPTH, fault: None, paused: true, mem_view: vec![], opcode: 0, mnemonic: String::new(), load_error: None}
     }
 
-    pub fn from_emul(chip: &Chip8, start: u16, end: u16) -> Self {
-        let mut stack = [0u8; 32];
-        stack.copy_from_slice(&chip.memory.address_space[0x50..0x70]);
+    pub fn from_emul(chip: &Chip8, start: u16, end: u16, paused: bool) -> Self {
         Status {
             pc: chip.cpu.program_counter,
             sp: chip.cpu.stack_pointer,
@@ -500,10 +1202,19 @@ impl Status {
             dt: chip.cpu.delay,
             st: chip.cpu.sound,
             v: chip.cpu.v_registers.clone(),
-            stack: stack,
+            call_stack: chip.call_stack(),
+            stack_capacity: chip.stack.depth,
+            fault: chip.fault.map(|fault| fault.to_string()),
+            paused,
             mem_view: chip.memory.address_space[start as usize..=end as usize].to_vec(),
             opcode: chip.opcode,
-            mnemonic: Chip8::get_mnemonic(chip.opcode)
+            mnemonic: Chip8::get_mnemonic(chip.opcode),
+            load_error: None
         }
     }
+
+    pub fn with_load_error(mut self, error: String) -> Self {
+        self.load_error = Some(error);
+        self
+    }
 }
\ No newline at end of file
@@ -1,170 +1,294 @@
 #![cfg_attr(feature = "release-ver", windows_subsystem = "windows")]
 
-mod chip8;
-use chip8::{Chip8};
+#[cfg(any(feature = "debug-ver", feature = "release-ver", feature = "tui-ver"))]
+use chip8::Chip8;
 
 #[cfg(feature = "debug-ver")]
-use std::{thread, time, sync::mpsc};
+use std::{thread, time, sync::mpsc::{self, Receiver, Sender, TryRecvError}, collections::VecDeque};
 #[cfg(feature = "debug-ver")]
 mod debugui;
 #[cfg(feature = "debug-ver")]
-use debugui::{DebugUI, Command, Status};
+mod romwatch;
+#[cfg(feature = "debug-ver")]
+use debugui::{DebugUI, Command, Status, Update};
+#[cfg(feature = "debug-ver")]
+use chip8::frontend::{VideoSink, InputSource, InputEvent};
+#[cfg(any(feature = "debug-ver", feature = "release-ver"))]
+use std::{rc::Rc, cell::Cell};
+// When the emulator thread pauses again after a step over, step out or run to
+#[cfg(feature = "debug-ver")]
+#[derive(Clone, Copy)]
+enum StopAt {
+    // Back from the CALL at this depth
+    Return { depth: usize, address: u16 },
+    // Out of the function running at this depth
+    Out { depth: usize },
+    // About to run the instruction at this address
+    Address(u16),
+    // After this many more frames
+    Frames(u32)
+}
+#[cfg(feature = "debug-ver")]
+impl StopAt {
+
+    // Called after every instruction
+    fn reached(&mut self, chip: &Chip8, frame_ended: bool) -> bool {
+        match self {
+            StopAt::Return { depth, address } => chip.stack_depth() < *depth
+                || (chip.stack_depth() == *depth && chip.cpu.read_pc() == *address),
+            StopAt::Out { depth } => chip.stack_depth() < *depth,
+            StopAt::Address(address) => chip.cpu.read_pc() == *address,
+            StopAt::Frames(left) => {
+                if frame_ended { *left = left.saturating_sub(1) }
+                *left == 0
+            }
+        }
+    }
+}
 #[cfg(feature = "debug-ver")]
 fn run_debug_ver() -> eframe::Result<()> {
     let (tx, rx) = mpsc::channel();
-    let (tx_keyboard, rx_keyboard) = mpsc::channel();
-    let (tx_framebuffer, rx_framebuffer) = mpsc::channel();
-    let (tx_status, rx_status) = mpsc::channel();
+    let (tx_updates, rx_updates) = mpsc::channel();
 
     thread::spawn(move || {
-        
-        // Emulator execution logic
         let mut chip = Chip8::new();
+        chip.seed_rng(clock_seed());
+
+        let frame_ended = Rc::new(Cell::new(false));
+        let mut video = DebugVideo { updates: tx_updates.clone(), frame_ended: frame_ended.clone() };
+        let mut input = DebugInput::new(rx, tx_updates, frame_ended);
+        loop {
+            chip.run_with_frontend(&mut video, &mut (), &mut input);
+            if chip.exit { break }
+            // Stopped by a fault, the program can still be looked at or another one loaded
+            input.control(&mut chip);
+        }
+    });
 
-        let mut start = time::Instant::now();
-        let mut end = time::Instant::now();
-        let mut accumulator = 0.0f64;
-        let mut threshold = 1.0/540.0;
-        let mut cycles = 0u8;
-
-        let mut running = true;
-        let mut paused = true;
-
-        let mut snapshot = false;
-        let mut keep_sending = false;
-        
-        let (mut s, mut e): (u16, u16) = (0x0200, 0x020F);
-
-        while running {
-
-            // Handle messages
-            for cmd in rx.try_iter() {
-                match cmd {
-                    Command::Exit => running = false,
-                    Command::Pause => paused = true,
-                    Command::Resume => paused = false,
-
-                    Command::Snapshot(start_addr, end_addr) => {
-                        (s, e) = (start_addr, end_addr);
-                        snapshot = true
-                    },
-
-                    Command::Fetch => if paused { chip.fetch() }
-                    Command::Execute => if paused {
-                        chip.decode_execute();
-                        cycles += 1;
-                        if cycles == 9 {
-                            chip.update_st();
-                            chip.update_dt();
-                            cycles = 0;
-                        }
-                    }
-                    Command::Step => if paused { 
-                        chip.fetch();
-                        chip.decode_execute();
-                        cycles += 1;
-                        if cycles == 9 {
-                            chip.update_st();
-                            chip.update_dt();
-                            cycles = 0;
-                        }
-                    }
+    let image = image::load_from_memory(include_bytes!("..\\icons\\logo-debug.png"))
+        .unwrap().into_rgba8();
+    let (w, h) = image.dimensions();
 
-                    Command::KeyDown(key) => {
-                        chip.keypad.set_key(key, true);
-                        // If waiting for key, resume
-                        if chip.waiting_for_key {
-                            chip.resume_ld_vx_k(key);
-                        }
-                        println!("Key {} is now DOWN", key);
-                    }
+    let config = chip8::config::Config::load();
+    let mut viewport = egui::ViewportBuilder::default().with_inner_size(config.debugger.window_size).with_icon(
+        std::sync::Arc::new(egui::IconData {
+            rgba: image.into_raw(),
+            width: w,
+            height: h
+        })
+    );
+    if let Some(position) = config.debugger.window_position {
+        viewport = viewport.with_position(position);
+    }
 
-                    Command::KeyUp(key) => {
-                        chip.keypad.set_key(key, false);
-                        println!("Key {} is now UP", key);
-                    }
+    let options = eframe::NativeOptions {
+        viewport,
+        ..Default::default()
+    };
+    
+    eframe::run_native(
+        "Chip8 Emulatxr Debugger", 
+        options, 
+        Box::new(|_cc| Ok(
+            Box::new(
+                DebugUI::new(tx, rx_updates, config)
+            )
+        ))
+    )
+}
+// Sends every frame to the debugger
+#[cfg(feature = "debug-ver")]
+struct DebugVideo {
+    updates: Sender<Update>,
+    // Tells DebugInput that a frame ended, to count frames to run
+    frame_ended: Rc<Cell<bool>>
+}
+#[cfg(feature = "debug-ver")]
+impl VideoSink for DebugVideo {
+    fn present(&mut self, chip: &Chip8) {
+        _ = self.updates.send(Update::Frame(Box::new(chip.display.screen)));
+        self.frame_ended.set(true);
+    }
+}
+// Takes the debugger's commands, holding the emulator while it is paused
+#[cfg(feature = "debug-ver")]
+struct DebugInput {
+    commands: Receiver<Command>,
+    updates: Sender<Update>,
+    frame_ended: Rc<Cell<bool>>,
+    // Commands for the emulator itself, which poll leaves to control
+    pending: VecDeque<Command>,
+    // The debugger is gone
+    closed: bool,
+    paused: bool,
+    stop_at: Option<StopAt>,
+    snapshot: bool,
+    keep_sending: bool,
+    memory_view: (u16, u16),
+    // As last sent to the debugger
+    keyboard: [u8; 16]
+}
+#[cfg(feature = "debug-ver")]
+impl DebugInput {
+    fn new(commands: Receiver<Command>, updates: Sender<Update>, frame_ended: Rc<Cell<bool>>) -> Self {
+        DebugInput {
+            commands,
+            updates,
+            frame_ended,
+            pending: VecDeque::new(),
+            closed: false,
+            paused: true,
+            stop_at: None,
+            snapshot: false,
+            keep_sending: false,
+            memory_view: (0x0200, 0x020F),
+            keyboard: [0; 16]
+        }
+    }
 
-                    Command::LoadRom(rom) => {
-                        paused = true;
-                        chip.insert_rom(rom);
-                        chip.reset();
-                    }
+    // Stopped where it was asked to, or on a fault, show where that is
+    fn pause(&mut self) {
+        self.paused = true;
+        self.stop_at = None;
+        self.snapshot = true;
+    }
 
-                    Command::ChangeFreq(freq) => {
-                        threshold = 1.0/(freq as f64);
-                    }
+    // Returns true if a single instruction should run now
+    fn handle(&mut self, chip: &mut Chip8, command: Command) -> bool {
+        match command {
+            Command::Input(event) => chip.apply_event(event),
+            Command::Pause => self.pause(),
+            Command::Resume => self.paused = false,
 
-                    Command::Continuous(keep) => {
-                        keep_sending = keep;
-                    }
-                }
+            Command::Snapshot(start, end) => {
+                self.memory_view = (start, end);
+                self.snapshot = true;
             }
 
-            if paused {
-                thread::sleep(time::Duration::from_millis(3000));
+            Command::Fetch => if self.paused { chip.fetch() }
+            // Single steps count towards the frame like any other instruction
+            Command::Execute => if self.paused { _ = chip.execute() }
+            Command::Step => return self.paused,
+            // A CALL runs until it returns, anything else is a single step
+            Command::StepOver => if self.paused {
+                let pc = chip.cpu.read_pc();
+                self.snapshot = true;
+                if !chip.memory.contains(pc, 2) || chip.memory.read_u16(pc) & 0xF000 != 0x2000 {
+                    return true;
+                }
+                self.stop_at = Some(StopAt::Return { depth: chip.stack_depth(), address: pc + 2 });
+                self.paused = false;
+            }
+            Command::StepOut => if self.paused && chip.stack_depth() > 0 {
+                self.stop_at = Some(StopAt::Out { depth: chip.stack_depth() });
+                self.paused = false;
+            }
+            Command::RunToAddress(address) => if self.paused {
+                self.stop_at = Some(StopAt::Address(address & 0x0FFF));
+                self.paused = false;
+            }
+            Command::RunFrames(frames) => if self.paused && frames > 0 {
+                self.stop_at = Some(StopAt::Frames(frames));
+                self.paused = false;
             }
 
-            else {
-                let delta = end - start;
-                start = time::Instant::now();
-                accumulator += delta.as_secs_f64();
-
-                while accumulator >= threshold {
-                    chip.fetch();
-                    chip.decode_execute();
-                    cycles += 1;
-                    if cycles == 9 {
-                        chip.update_st();
-                        chip.update_dt();
-                        cycles = 0;
+            Command::LoadRom(rom) => {
+                self.pause();
+                match chip.insert_rom(rom) {
+                    Ok(()) => chip.reset(),
+                    // The debugger took the ROM as loaded, tell it why it isn't
+                    Err(e) => {
+                        let (start, end) = self.memory_view;
+                        let status = Status::from_emul(chip, start, end, self.paused).with_load_error(e.to_string());
+                        _ = self.updates.send(Update::Status(status));
                     }
-                    accumulator -= threshold;
                 }
-                end = time::Instant::now();
             }
 
-            _ = tx_keyboard.send(chip.keypad.keys);
+            Command::SetCyclesPerFrame(cycles) => chip.cycles_per_frame = cycles,
+            Command::Continuous(keep) => self.keep_sending = keep,
+            Command::SetQuirks(quirks) => chip.quirks = quirks,
+            Command::SetTiming(timing) => chip.timing = timing,
 
-            if chip.new_draw {
-                _ = tx_framebuffer.send(
-                    chip.display.screen
-                );
-                chip.new_draw = false;
+            // The address is already limited by the debugger
+            Command::SetFont(font, base) => {
+                _ = chip.set_font(*font, base);
+                self.snapshot = true;
             }
 
-            if snapshot || keep_sending {
-                _ = tx_status.send(
-                    Status::from_emul(&chip, s, e)
-                );
-                snapshot = false;
+            Command::SetStack(depth, storage) => {
+                chip.stack.depth = depth;
+                chip.stack.storage = storage;
+                self.snapshot = true;
             }
         }
-    });
+        false
+    }
 
-    let image = image::load_from_memory(include_bytes!("..\\icons\\logo-debug.png"))
-        .unwrap().into_rgba8();
-    let (w, h) = image.dimensions();
+    fn send_updates(&mut self, chip: &mut Chip8) {
+        if chip.keypad.keys != self.keyboard {
+            self.keyboard = chip.keypad.keys;
+            _ = self.updates.send(Update::Keyboard(self.keyboard));
+        }
+        // Single steps are shown right away instead of waiting for the frame to end
+        if self.paused && chip.new_draw {
+            _ = self.updates.send(Update::Frame(Box::new(chip.display.screen)));
+            chip.new_draw = false;
+        }
+        if self.snapshot || (self.paused && self.keep_sending) {
+            let (start, end) = self.memory_view;
+            _ = self.updates.send(Update::Status(Status::from_emul(chip, start, end, self.paused)));
+            self.snapshot = false;
+        }
+    }
+}
+#[cfg(feature = "debug-ver")]
+impl InputSource for DebugInput {
+    fn poll(&mut self) -> Option<InputEvent> {
+        loop {
+            match self.commands.try_recv() {
+                Ok(Command::Input(event)) => return Some(event),
+                Ok(command) => self.pending.push_back(command),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    return None;
+                }
+            }
+        }
+    }
 
-    let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([900.0, 600.0]).with_icon(
-            std::sync::Arc::new(egui::IconData {
-                rgba: image.into_raw(),
-                width: w,
-                height: h
-            })
-        ),
-        ..Default::default()
-    };
-    
-    eframe::run_native(
-        "Chip8 Emulatxr Debugger", 
-        options, 
-        Box::new(|_cc| Ok(
-            Box::new(
-                DebugUI::new(tx, rx_framebuffer, rx_status, rx_keyboard)
-            )
-        ))
-    )
+    fn control(&mut self, chip: &mut Chip8) {
+        let frame_ended = self.frame_ended.take();
+        if let Some(stop) = &mut self.stop_at && stop.reached(chip, frame_ended) {
+            self.pause();
+        }
+        // A fault halts the program, so there is nothing left to run
+        if chip.fault.is_some() && !self.paused {
+            self.pause();
+        }
+        if self.keep_sending && frame_ended {
+            self.snapshot = true;
+        }
+
+        loop {
+            while let Some(command) = self.pending.pop_front() {
+                // The status is sent after the step, on the next call
+                if self.handle(chip, command) { return }
+            }
+            self.send_updates(chip);
+            if self.closed {
+                chip.exit = true;
+            }
+            if !self.paused || chip.exit { return }
+
+            // Short enough that keys clicked on the virtual keypad show up right away while stepping
+            thread::sleep(time::Duration::from_millis(10));
+            while let Some(event) = self.poll() {
+                chip.apply_event(event);
+            }
+        }
+    }
 }
 
 #[cfg(feature = "release-ver")]
@@ -180,131 +304,501 @@ fn set_window_icon(window: &mut sdl2::video::Window) -> Result<(), String> {
 #[cfg(feature = "release-ver")]
 use sdl2::{
     event::Event,
-    keyboard::Keycode,
-    pixels::{PixelFormatEnum},
-    surface::Surface, rwops::RWops, image::{LoadSurface, InitFlag},
+    keyboard::{Keycode, Mod},
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Canvas, Texture},
+    video::{FullscreenType, Window},
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    rwops::RWops, image::InitFlag,
 };
 #[cfg(feature = "release-ver")]
+use chip8::{beeper::Beeper, frontend::{VideoSink, AudioSink, InputSource, InputEvent}, postprocess::{self, Filter, PostProcess}, capture::{self, Recorder}, palette::Palette, timing::Speed, viewport::{Overlay, Scaling, Viewport}};
+#[cfg(feature = "release-ver")]
+use std::cell::RefCell;
+#[cfg(feature = "release-ver")]
 fn run_release_ver() -> Result<(), String> {
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
         return Err("No ROM selected".to_owned());
     }
     let game_path = &args[1];
-    
+    // Read rom from args
+    let rom = rom_from_args(&args)?;
+    let info = chip8::romdb::RomDb::load().lookup(&rom.data).cloned();
+
+    let filter = filter_from_args(&args, game_path)?;
+    let palette = palette_from_args(&args, game_path, info.as_ref())?;
+    let scaling = match args.iter().position(|arg| arg == "--scaling") {
+        Some(i) => args.get(i + 1)
+            .and_then(|name| Scaling::from_name(name))
+            .ok_or("Unknown scaling, expected integer or fit".to_owned())?,
+        None => Scaling::Integer
+    };
+    let overlay = match args.iter().position(|arg| arg == "--overlay") {
+        Some(i) => args.get(i + 1)
+            .and_then(|name| Overlay::from_name(name))
+            .ok_or("Unknown overlay, expected none, grid or scanlines".to_owned())?,
+        None => Overlay::None
+    };
 
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
+    let audio_subsystem = sdl_context.audio()?;
 
+    let title = match &info {
+        Some(info) if !info.title.is_empty() => format!("Chip8 Emulatxr - {}", info.title),
+        _ => "Chip8 Emulatxr".to_owned()
+    };
     let mut window = video_subsystem
-        .window("Chip8 Emulatxr", 640, 320)
+        .window(&title, 640, 320)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
     set_window_icon(&mut window)?;
+    window.set_minimum_size(64, 32).map_err(|e| e.to_string())?;
 
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
-    let mut event_pump = sdl_context.event_pump()?;
-    canvas.set_scale(10.0, 10.0).map_err(|e| e.to_string())?;
+    let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let event_pump = sdl_context.event_pump()?;
 
     let creator = canvas.texture_creator();
-    let mut texture = creator
+    let texture = creator
         .create_texture_target(
             PixelFormatEnum::RGB24,
             64,
             32
         ).map_err(|e| e.to_string())?;
 
-    // Read rom from args
-    let program = std::fs::read(game_path).map_err(|_| "Could not read game rom".to_owned())?;
+    let spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None
+    };
+    let device = audio_subsystem.open_playback(None, &spec, |spec| {
+        SdlBeeper(Beeper::new(spec.freq as u32))
+    })?;
 
     let mut chip = Chip8::new();
-    chip.insert_rom(program);
+    chip.seed_rng(clock_seed());
+    chip.quirks = quirks_from_args(&args, info.as_ref());
+    chip.timing = timing_from_args(&args)?;
+    chip.cycles_per_frame = cycles_per_frame(&args, info.as_ref())?;
+    chip.stack = stack_from_args(&args)?;
+    let (font, font_base) = font_from_args(&args)?;
+    chip.set_font(font, font_base)?;
+    chip.insert_rom(rom.data).map_err(|e| e.to_string())?;
 
     chip.load();
-    chip.run_with_callbacks(
-        move |chip| {
-            if let Some(_) = handle_user_input(chip, &mut event_pump) { chip.exit = true; }
+    let capture = Rc::new(RefCell::new(SdlCapture { screenshot: false, recorder: None, palette }));
+    let toggle_fullscreen = Rc::new(Cell::new(false));
+    chip.run_with_frontend(
+        &mut SdlVideo {
+            canvas,
+            texture,
+            post: PostProcess::new(filter),
+            capture: capture.clone(),
+            scaling,
+            overlay,
+            toggle_fullscreen: toggle_fullscreen.clone()
         },
-        move |chip| {
-            if chip.new_draw {
-
-                // Draw screen
-                let screen_state = map_chip_display(&chip.display);
-                texture.update(None, &screen_state, 64 * 3).ok();
-                canvas.copy(&texture, None, None).ok();
-                canvas.present();
+        &mut SdlAudio { device },
+        &mut SdlInput { event_pump, capture, toggle_fullscreen }
+    );
 
-                chip.new_draw = false;
+    fault_result(&chip)
+}
+#[cfg(feature = "release-ver")]
+struct SdlCapture {
+    screenshot: bool,
+    recorder: Option<Recorder>,
+    palette: Palette
+}
+#[cfg(feature = "release-ver")]
+impl SdlCapture {
+    fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let path = capture::timestamped_name("recording", "gif");
+                match recorder.finish(&path) {
+                    Ok(_) => println!("Saved recording to {}", path),
+                    Err(e) => eprintln!("Could not save recording: {}", e)
+                }
             }
-        },
-        540.0
-    );
+            None => self.recorder = Some(Recorder::new(CAPTURE_SCALE, self.palette.on(), self.palette.off()))
+        }
+    }
+}
+#[cfg(feature = "release-ver")]
+const CAPTURE_SCALE: u32 = 10;
+#[cfg(feature = "release-ver")]
+const OVERLAY_ALPHA: u8 = 96;
+#[cfg(feature = "release-ver")]
+struct SdlVideo<'a> {
+    canvas: Canvas<Window>,
+    texture: Texture<'a>,
+    post: PostProcess,
+    capture: Rc<RefCell<SdlCapture>>,
+    scaling: Scaling,
+    overlay: Overlay,
+    toggle_fullscreen: Rc<Cell<bool>>
+}
+#[cfg(feature = "release-ver")]
+impl SdlVideo<'_> {
+    fn switch_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off
+        };
+        if let Err(e) = window.set_fullscreen(state) {
+            eprintln!("Could not switch fullscreen: {}", e);
+        }
+    }
+}
+#[cfg(feature = "release-ver")]
+impl VideoSink for SdlVideo<'_> {
+    fn present(&mut self, chip: &Chip8) {
+        let mut capture = self.capture.borrow_mut();
+        if capture.screenshot {
+            let path = capture::timestamped_name("screenshot", "png");
+            match capture::save_screenshot(&chip.display.screen, CAPTURE_SCALE, capture.palette.on(), capture.palette.off(), &path) {
+                Ok(_) => println!("Saved screenshot to {}", path),
+                Err(e) => eprintln!("Could not save screenshot: {}", e)
+            }
+            capture.screenshot = false;
+        }
+        if let Some(recorder) = &mut capture.recorder {
+            recorder.push(&chip.display.screen);
+        }
 
-    Ok(())
+        self.post.process(&chip.display.screen);
+        let screen_state = map_chip_display(self.post.output(), &capture.palette);
+        self.texture.update(None, &screen_state, 64 * 3).ok();
+        drop(capture);
+
+        if self.toggle_fullscreen.take() {
+            self.switch_fullscreen();
+        }
+        let (width, height) = self.canvas.output_size().unwrap_or((640, 320));
+        let view = Viewport::fit(width, height, self.scaling);
+
+        // Letterbox around the screen
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, Rect::new(view.x, view.y, view.width, view.height)).ok();
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, OVERLAY_ALPHA));
+        for (from, to) in view.overlay_lines(self.overlay) {
+            self.canvas.draw_line(from, to).ok();
+        }
+        self.canvas.present();
+    }
+}
+#[cfg(feature = "release-ver")]
+struct SdlBeeper(Beeper);
+#[cfg(feature = "release-ver")]
+impl AudioCallback for SdlBeeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out);
+    }
 }
 #[cfg(feature = "release-ver")]
-fn map_chip_display(display: &chip8::display::Display) -> Vec<u8> {
-    let screen_state: Vec<u8> = display.screen
+struct SdlAudio {
+    device: AudioDevice<SdlBeeper>
+}
+#[cfg(feature = "release-ver")]
+impl AudioSink for SdlAudio {
+    fn set_tone(&mut self, on: bool) {
+        if on { self.device.resume() } else { self.device.pause() }
+    }
+}
+#[cfg(feature = "release-ver")]
+struct SdlInput {
+    event_pump: sdl2::EventPump,
+    capture: Rc<RefCell<SdlCapture>>,
+    toggle_fullscreen: Rc<Cell<bool>>
+}
+#[cfg(feature = "release-ver")]
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> Option<InputEvent> {
+        while let Some(event) = self.event_pump.poll_event() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    // Don't lose a recording in progress
+                    let mut capture = self.capture.borrow_mut();
+                    if capture.recorder.is_some() { capture.toggle_recording() }
+                    return Some(InputEvent::Quit)
+                }
+
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    self.capture.borrow_mut().screenshot = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    self.capture.borrow_mut().toggle_recording();
+                }
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    self.toggle_fullscreen.set(true);
+                }
+
+                // Held down, Tab fast-forwards (with Shift as fast as possible) and ` slows down
+                Event::KeyDown { keycode: Some(Keycode::Tab), keymod, repeat: false, .. } => {
+                    let uncapped = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    return Some(InputEvent::SetSpeed(if uncapped { Speed::Uncapped } else { Speed::FAST_FORWARD }))
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backquote), repeat: false, .. } => {
+                    return Some(InputEvent::SetSpeed(Speed::SLOW_MOTION))
+                }
+                Event::KeyUp { keycode: Some(Keycode::Tab | Keycode::Backquote), .. } => {
+                    return Some(InputEvent::SetSpeed(Speed::Normal))
+                }
+
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(key) = map_keycode(keycode) { return Some(InputEvent::KeyDown(key)) }
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(key) = map_keycode(keycode) { return Some(InputEvent::KeyUp(key)) }
+                }
+
+                _ => {}
+            }
+        }
+        None
+    }
+}
+#[cfg(feature = "release-ver")]
+fn map_chip_display(intensities: &[[f32; 64]; 32], palette: &Palette) -> Vec<u8> {
+    let screen_state: Vec<u8> = intensities
         .iter()
         .flat_map(|row| {
-            row.iter().flat_map(|pixel| {
-                if *pixel == 0 { [0, 0, 0] }
-                else { [255, 255, 255] }
+            row.iter().flat_map(|intensity| {
+                postprocess::mix(palette.off(), palette.on(), *intensity)
             })
         })
         .collect();
     screen_state
 }
 #[cfg(feature = "release-ver")]
-fn handle_user_input(chip: &mut Chip8, event_pump: &mut sdl2::EventPump) -> Option<()> {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => return Some(()),
-
-            Event::KeyDown { keycode: Some(Keycode::Num1), .. } => { chip.keypad.set_key(0x01, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x01) } },
-            Event::KeyDown { keycode: Some(Keycode::Num2), .. } => { chip.keypad.set_key(0x02, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x02) } },
-            Event::KeyDown { keycode: Some(Keycode::Num3), .. } => { chip.keypad.set_key(0x03, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x03) } },
-            Event::KeyDown { keycode: Some(Keycode::Num4), .. } => { chip.keypad.set_key(0x0C, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0C) } },
-            Event::KeyDown { keycode: Some(Keycode::Q), .. } => { chip.keypad.set_key(0x04, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x04) } },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => { chip.keypad.set_key(0x05, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x05) } },
-            Event::KeyDown { keycode: Some(Keycode::E), .. } => { chip.keypad.set_key(0x06, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x06) } },
-            Event::KeyDown { keycode: Some(Keycode::R), .. } => { chip.keypad.set_key(0x0D, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0D) } },
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => { chip.keypad.set_key(0x07, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x07) } },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => { chip.keypad.set_key(0x08, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x08) } },
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => { chip.keypad.set_key(0x09, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x09) } },
-            Event::KeyDown { keycode: Some(Keycode::F), .. } => { chip.keypad.set_key(0x0E, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0E) } },
-            Event::KeyDown { keycode: Some(Keycode::Z), .. } => { chip.keypad.set_key(0x0A, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0A) } },
-            Event::KeyDown { keycode: Some(Keycode::X), .. } => { chip.keypad.set_key(0x00, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x00) } },
-            Event::KeyDown { keycode: Some(Keycode::C), .. } => { chip.keypad.set_key(0x0B, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0B) } },
-            Event::KeyDown { keycode: Some(Keycode::V), .. } => { chip.keypad.set_key(0x0F, true); if chip.waiting_for_key { chip.resume_ld_vx_k(0x0F) } },
-
-            Event::KeyUp { keycode: Some(Keycode::Num1), .. } => { chip.keypad.set_key(0x01, false) },
-            Event::KeyUp { keycode: Some(Keycode::Num2), .. } => { chip.keypad.set_key(0x02, false) },
-            Event::KeyUp { keycode: Some(Keycode::Num3), .. } => { chip.keypad.set_key(0x03, false) },
-            Event::KeyUp { keycode: Some(Keycode::Num4), .. } => { chip.keypad.set_key(0x0C, false) },
-            Event::KeyUp { keycode: Some(Keycode::Q), .. } => { chip.keypad.set_key(0x04, false) },
-            Event::KeyUp { keycode: Some(Keycode::W), .. } => { chip.keypad.set_key(0x05, false) },
-            Event::KeyUp { keycode: Some(Keycode::E), .. } => { chip.keypad.set_key(0x06, false) },
-            Event::KeyUp { keycode: Some(Keycode::R), .. } => { chip.keypad.set_key(0x0D, false) },
-            Event::KeyUp { keycode: Some(Keycode::A), .. } => { chip.keypad.set_key(0x07, false) },
-            Event::KeyUp { keycode: Some(Keycode::S), .. } => { chip.keypad.set_key(0x08, false) },
-            Event::KeyUp { keycode: Some(Keycode::D), .. } => { chip.keypad.set_key(0x09, false) },
-            Event::KeyUp { keycode: Some(Keycode::F), .. } => { chip.keypad.set_key(0x0E, false) },
-            Event::KeyUp { keycode: Some(Keycode::Z), .. } => { chip.keypad.set_key(0x0A, false) },
-            Event::KeyUp { keycode: Some(Keycode::X), .. } => { chip.keypad.set_key(0x00, false) },
-            Event::KeyUp { keycode: Some(Keycode::C), .. } => { chip.keypad.set_key(0x0B, false) },
-            Event::KeyUp { keycode: Some(Keycode::V), .. } => { chip.keypad.set_key(0x0F, false) },
-
-            _ => {}
-        }
+fn map_keycode(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num1 => Some(0x01),
+        Keycode::Num2 => Some(0x02),
+        Keycode::Num3 => Some(0x03),
+        Keycode::Num4 => Some(0x0C),
+        Keycode::Q => Some(0x04),
+        Keycode::W => Some(0x05),
+        Keycode::E => Some(0x06),
+        Keycode::R => Some(0x0D),
+        Keycode::A => Some(0x07),
+        Keycode::S => Some(0x08),
+        Keycode::D => Some(0x09),
+        Keycode::F => Some(0x0E),
+        Keycode::Z => Some(0x0A),
+        Keycode::X => Some(0x00),
+        Keycode::C => Some(0x0B),
+        Keycode::V => Some(0x0F),
+        _ => None
+    }
+}
+
+#[cfg(feature = "tui-ver")]
+mod tui;
+#[cfg(feature = "tui-ver")]
+fn run_tui_ver() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        return Err("No ROM selected".to_owned());
+    }
+    let rom = rom_from_args(&args)?;
+    let info = chip8::romdb::RomDb::load().lookup(&rom.data).cloned();
+    let palette = palette_from_args(&args, &args[1], info.as_ref())?;
+
+    let mut chip = Chip8::new();
+    chip.seed_rng(clock_seed());
+    chip.quirks = quirks_from_args(&args, info.as_ref());
+    chip.timing = timing_from_args(&args)?;
+    chip.cycles_per_frame = cycles_per_frame(&args, info.as_ref())?;
+    chip.stack = stack_from_args(&args)?;
+    let (font, font_base) = font_from_args(&args)?;
+    chip.set_font(font, font_base)?;
+    chip.insert_rom(rom.data).map_err(|e| e.to_string())?;
+    chip.load();
+
+    let terminal = tui::TerminalGuard::new().map_err(|e| e.to_string())?;
+    chip.run_with_frontend(
+        &mut tui::TuiVideo::new(palette, info),
+        &mut tui::TuiAudio,
+        &mut tui::TuiInput::new(&terminal)
+    );
+    fault_result(&chip)
+}
+
+#[cfg(any(feature = "debug-ver", feature = "release-ver", feature = "tui-ver"))]
+fn clock_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos() ^ elapsed.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn rom_from_args(args: &[String]) -> Result<chip8::rom::Rom, String> {
+    use chip8::rom::{Platform, RomLoader};
+
+    let loader = match args.iter().position(|arg| arg == "--platform") {
+        Some(i) => RomLoader::with_platform(args.get(i + 1)
+            .and_then(|name| Platform::from_name(name))
+            .ok_or("Unknown platform, expected chip-8, schip or xo-chip".to_owned())?),
+        None => RomLoader::new()
+    };
+    loader.load_file(&args[1]).map_err(|e| e.to_string())
+}
+
+// Picks the palette named by --palette, remembering it for this ROM,
+// otherwise the one last used with it or the one from the ROM database
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn palette_from_args(args: &[String], game_path: &str, info: Option<&chip8::romdb::RomInfo>) -> Result<chip8::palette::Palette, String> {
+    use chip8::{config::Config, palette::Palette};
+
+    let mut config = Config::load();
+    let rom = std::path::Path::new(game_path);
+    let Some(i) = args.iter().position(|arg| arg == "--palette") else {
+        return Ok(config.palette_for(Some(rom), info.and_then(|info| info.palette.as_deref())));
+    };
+
+    let palettes = config.all_palettes();
+    let palette = args.get(i + 1)
+        .and_then(|name| Palette::find(&palettes, name))
+        .cloned()
+        .ok_or_else(|| {
+            let names: Vec<&str> = palettes.iter().map(|palette| palette.name.as_str()).collect();
+            format!("Unknown palette, expected one of: {}", names.join(", "))
+        })?;
+    config.remember_palette(Some(rom), &palette.name);
+    if let Err(e) = config.save() {
+        eprintln!("{}", e);
+    }
+    Ok(palette)
+}
+
+// Picks the filter named by --filter, remembering it for this ROM,
+// otherwise the one last used with it
+#[cfg(feature = "release-ver")]
+fn filter_from_args(args: &[String], game_path: &str) -> Result<Filter, String> {
+    use chip8::config::Config;
+
+    let mut config = Config::load();
+    let rom = std::path::Path::new(game_path);
+    let Some(i) = args.iter().position(|arg| arg == "--filter") else {
+        return Ok(config.filter_for(rom));
+    };
+
+    let filter = args.get(i + 1)
+        .and_then(|name| Filter::from_name(name))
+        .ok_or("Unknown filter, expected none, phosphor or blend".to_owned())?;
+    config.remember_filter(rom, filter);
+    if let Err(e) = config.save() {
+        eprintln!("{}", e);
+    }
+    Ok(filter)
+}
+
+// The ROM database sets the quirks a game needs, the command line can only turn more on
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn quirks_from_args(args: &[String], info: Option<&chip8::romdb::RomInfo>) -> chip8::quirks::Quirks {
+    let mut quirks = chip8::quirks::Quirks::new();
+    if let Some(info) = info {
+        info.apply_quirks(&mut quirks);
+    }
+    if args.iter().any(|arg| arg == "--display-wait") {
+        quirks.display_wait = true;
+    }
+    if args.iter().any(|arg| arg == "--wait-for-release") {
+        quirks.wait_for_release = true;
+    }
+    quirks
+}
+
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn timing_from_args(args: &[String]) -> Result<chip8::timing::Timing, String> {
+    match args.iter().position(|arg| arg == "--timing") {
+        Some(i) => args.get(i + 1)
+            .and_then(|name| chip8::timing::Timing::from_name(name))
+            .ok_or("Unknown timing, expected fixed or vip".to_owned()),
+        None => Ok(chip8::timing::Timing::Fixed)
+    }
+}
+
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn stack_from_args(args: &[String]) -> Result<chip8::stack::Stack, String> {
+    use chip8::stack::{Stack, StackStorage, MAX_STACK_DEPTH};
+
+    let mut stack = Stack::new();
+    if let Some(i) = args.iter().position(|arg| arg == "--stack-depth") {
+        stack.depth = args.get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .filter(|depth| (1..=MAX_STACK_DEPTH).contains(depth))
+            .ok_or(format!("Stack depth must be between 1 and {}", MAX_STACK_DEPTH))?;
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--stack") {
+        stack.storage = args.get(i + 1)
+            .and_then(|name| StackStorage::from_name(name))
+            .ok_or("Unknown stack, expected memory or isolated".to_owned())?;
+    }
+    Ok(stack)
+}
+
+// A built-in font by name or one read from a file, and the address it is loaded at, in hex
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn font_from_args(args: &[String]) -> Result<(chip8::font::Font, u16), String> {
+    use chip8::font::{Font, FontSet, DEFAULT_FONT_BASE};
+
+    let mut font = Font::default();
+    if let Some(i) = args.iter().position(|arg| arg == "--font") {
+        font = args.get(i + 1)
+            .and_then(|name| FontSet::from_name(name))
+            .ok_or("Unknown font, expected vip, dream6800, eti660 or schip".to_owned())?
+            .font();
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--font-file") {
+        let path = args.get(i + 1).ok_or("Missing font file".to_owned())?;
+        font = Font::from_file(std::path::Path::new(path))?;
+    }
+    let base = match args.iter().position(|arg| arg == "--font-base") {
+        Some(i) => args.get(i + 1)
+            .and_then(|text| u16::from_str_radix(text.trim_start_matches("0x"), 16).ok())
+            .ok_or("Invalid font base, expected a hex address".to_owned())?,
+        None => DEFAULT_FONT_BASE
+    };
+    Ok((font, base))
+}
+
+// The emulator stops on a fault, which is then reported like any other error
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn fault_result(chip: &Chip8) -> Result<(), String> {
+    match chip.fault {
+        Some(fault) => Err(format!("{} at {:#06x}", fault, chip.cpu.read_pc())),
+        None => Ok(())
+    }
+}
+
+#[cfg(any(feature = "release-ver", feature = "tui-ver"))]
+fn cycles_per_frame(args: &[String], info: Option<&chip8::romdb::RomInfo>) -> Result<usize, String> {
+    match args.iter().position(|arg| arg == "--cycles-per-frame") {
+        Some(i) => args.get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .filter(|cycles| *cycles > 0)
+            .ok_or("Cycles per frame must be a positive number".to_owned()),
+        None => Ok(info.and_then(|info| info.cycles_per_frame).map_or(chip8::DEFAULT_CYCLES_PER_FRAME, |cycles| cycles as usize))
     }
-    None
 }
 
 fn main() {
@@ -315,8 +809,18 @@ fn main() {
         Err(e) => eprintln!("{}", e),
     }
 
+    // There is no console to print to on Windows, so errors also get a message box
     #[cfg(feature = "release-ver")]
     match run_release_ver() {
+        Ok(_) => {},
+        Err(s) => {
+            eprintln!("{}", s);
+            _ = sdl2::messagebox::show_simple_message_box(sdl2::messagebox::MessageBoxFlag::ERROR, "Chip8 Emulatxr", &s, None);
+        }
+    }
+
+    #[cfg(feature = "tui-ver")]
+    match run_tui_ver() {
         Ok(_) => {},
         Err(s) => eprintln!("{}", s),
     }
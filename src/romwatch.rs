@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+// Tells when a ROM file is written to. The whole directory is watched, since
+// assemblers and editors often replace the file instead of writing into it
+pub struct RomWatcher {
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    path: PathBuf
+}
+
+impl RomWatcher {
+    pub fn new(path: &Path) -> notify::Result<Self> {
+        let path = path.canonicalize()?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(RomWatcher { _watcher: watcher, rx, path })
+    }
+
+    // True if the file changed since the last call
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.rx.try_iter().flatten() {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) && event.paths.contains(&self.path) {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::{
+    cursor, queue, execute,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags},
+    style::{Print, Color, SetColors, Colors, ResetColor},
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen, ClearType},
+};
+use chip8::{Chip8, frontend::{VideoSink, AudioSink, InputSource, InputEvent}, palette::Palette, romdb::RomInfo, timing::Speed};
+
+// Terminals without the kitty keyboard protocol never report releases,
+// so a key is considered released once it stops auto-repeating
+const RELEASE_AFTER: Duration = Duration::from_millis(300);
+
+const SIDE_PANE_COLUMN: u16 = 66;
+
+// Puts the terminal in raw mode for as long as it is alive
+pub struct TerminalGuard {
+    pub key_releases: bool
+}
+
+impl TerminalGuard {
+    pub fn new() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, cursor::Hide, terminal::Clear(ClearType::All))?;
+
+        let key_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if key_releases {
+            execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+
+        Ok(TerminalGuard { key_releases })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.key_releases {
+            _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        _ = execute!(stdout, cursor::Show, LeaveAlternateScreen);
+        _ = terminal::disable_raw_mode();
+    }
+}
+
+pub struct TuiVideo {
+    stdout: io::Stdout,
+    palette: Palette,
+    info: Option<RomInfo>
+}
+
+impl TuiVideo {
+    pub fn new(palette: Palette, info: Option<RomInfo>) -> Self {
+        TuiVideo { stdout: io::stdout(), palette, info }
+    }
+
+    fn draw_screen(&mut self, chip: &Chip8) -> io::Result<()> {
+        let screen = &chip.display.screen;
+        let ([r, g, b], [br, bg, bb]) = (self.palette.on(), self.palette.off());
+        queue!(self.stdout, SetColors(Colors::new(Color::Rgb { r, g, b }, Color::Rgb { r: br, g: bg, b: bb })))?;
+
+        // Every character cell covers two rows of pixels
+        for row in 0..16 {
+            let line: String = (0..64).map(|col| {
+                match (screen[row * 2][col], screen[row * 2 + 1][col]) {
+                    (0, 0) => ' ',
+                    (_, 0) => '▀',
+                    (0, _) => '▄',
+                    _ => '█'
+                }
+            }).collect();
+            queue!(self.stdout, cursor::MoveTo(1, row as u16 + 1), Print(line))?;
+        }
+        queue!(self.stdout, ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_registers(&mut self, chip: &Chip8) -> io::Result<()> {
+        let col = SIDE_PANE_COLUMN;
+        let cpu = &chip.cpu;
+
+        queue!(self.stdout, cursor::MoveTo(col, 1), Print(format!("Opcode: {:#06x}", chip.opcode)))?;
+        for i in 0..4 {
+            queue!(self.stdout,
+                cursor::MoveTo(col, 3 + i as u16),
+                Print(format!("V{:1x}: {:#04x}  V{:1x}: {:#04x}  V{:1x}: {:#04x}  V{:1x}: {:#04x}",
+                    i * 4, cpu.v_registers[i * 4],
+                    i * 4 + 1, cpu.v_registers[i * 4 + 1],
+                    i * 4 + 2, cpu.v_registers[i * 4 + 2],
+                    i * 4 + 3, cpu.v_registers[i * 4 + 3]
+                ))
+            )?;
+        }
+        queue!(self.stdout,
+            cursor::MoveTo(col, 8),
+            Print(format!("PC: {:#06x}  SP: {:#04x}  I: {:#06x}", cpu.program_counter, cpu.stack_pointer, cpu.i_register)),
+            cursor::MoveTo(col, 9),
+            Print(format!("DT: {:<3}  ST: {:<3}  {}", cpu.delay, cpu.sound, if cpu.sound > 0 { "BEEP" } else { "    " })),
+            cursor::MoveTo(col, 11),
+            Print(if chip.waiting_for_key { "Waiting for key" } else { "               " }),
+            cursor::MoveTo(col, 12),
+            Print(format!("Speed: {:<8}", chip.speed.label())),
+            cursor::MoveTo(col, 14),
+            Print("Esc to quit")
+        )?;
+        if let Some(info) = &self.info {
+            queue!(self.stdout, cursor::MoveTo(col, 16), Print(&info.title))?;
+            for (i, hint) in info.key_hints().iter().enumerate() {
+                queue!(self.stdout, cursor::MoveTo(col, 17 + i as u16), Print(hint))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VideoSink for TuiVideo {
+    fn present(&mut self, chip: &Chip8) {
+        // Redrawing an unchanged screen would only waste bandwidth over SSH
+        if chip.new_draw {
+            _ = self.draw_screen(chip);
+        }
+        _ = self.draw_registers(chip);
+        _ = self.stdout.flush();
+    }
+}
+
+// Rings the terminal bell whenever the sound timer starts
+pub struct TuiAudio;
+
+impl AudioSink for TuiAudio {
+    fn set_tone(&mut self, on: bool) {
+        if on {
+            _ = execute!(io::stdout(), Print('\x07'));
+        }
+    }
+}
+
+pub struct TuiInput {
+    key_releases: bool,
+    held: [Option<Instant>; 16],
+    speed: Speed
+}
+
+impl TuiInput {
+    pub fn new(terminal: &TerminalGuard) -> Self {
+        TuiInput {
+            key_releases: terminal.key_releases,
+            held: [None; 16],
+            speed: Speed::Normal
+        }
+    }
+
+    // Holding a key down can't be told apart from auto-repeat everywhere, so speeds are toggled
+    fn toggle_speed(&mut self, speed: Speed) -> InputEvent {
+        self.speed = if self.speed == speed { Speed::Normal } else { speed };
+        InputEvent::SetSpeed(self.speed)
+    }
+}
+
+impl InputSource for TuiInput {
+    fn poll(&mut self) -> Option<InputEvent> {
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            let Ok(Event::Key(key_event)) = event::read() else { continue };
+
+            if key_event.code == KeyCode::Esc
+                || (key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL)) {
+                return Some(InputEvent::Quit);
+            }
+            if key_event.kind == KeyEventKind::Press {
+                match key_event.code {
+                    KeyCode::Tab => return Some(self.toggle_speed(Speed::FAST_FORWARD)),
+                    KeyCode::BackTab => return Some(self.toggle_speed(Speed::Uncapped)),
+                    KeyCode::Char('`') => return Some(self.toggle_speed(Speed::SLOW_MOTION)),
+                    _ => {}
+                }
+            }
+
+            let Some(key) = map_keycode(key_event.code) else { continue };
+            let was_held = self.held[key as usize].is_some();
+            match key_event.kind {
+                KeyEventKind::Release => {
+                    self.held[key as usize] = None;
+                    return Some(InputEvent::KeyUp(key));
+                }
+                _ => {
+                    self.held[key as usize] = Some(Instant::now());
+                    if !was_held { return Some(InputEvent::KeyDown(key)) }
+                }
+            }
+        }
+
+        if !self.key_releases {
+            for key in 0..16 {
+                if let Some(pressed_at) = self.held[key] && pressed_at.elapsed() >= RELEASE_AFTER {
+                    self.held[key] = None;
+                    return Some(InputEvent::KeyUp(key as u8));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn map_keycode(code: KeyCode) -> Option<u8> {
+    let KeyCode::Char(c) = code else { return None };
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x01),
+        '2' => Some(0x02),
+        '3' => Some(0x03),
+        '4' => Some(0x0C),
+        'q' => Some(0x04),
+        'w' => Some(0x05),
+        'e' => Some(0x06),
+        'r' => Some(0x0D),
+        'a' => Some(0x07),
+        's' => Some(0x08),
+        'd' => Some(0x09),
+        'f' => Some(0x0E),
+        'z' => Some(0x0A),
+        'x' => Some(0x00),
+        'c' => Some(0x0B),
+        'v' => Some(0x0F),
+        _ => None
+    }
+}
@@ -0,0 +1,49 @@
+use wasm_bindgen::prelude::*;
+use chip8::{Chip8, rom::{RomLoader, EXTENSIONS}};
+
+#[wasm_bindgen]
+pub struct Emulator {
+    chip: Chip8
+}
+
+#[wasm_bindgen]
+impl Emulator {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Emulator {
+        let mut chip = Chip8::new();
+        chip.seed_rng(seed);
+        Emulator { chip }
+    }
+
+    // Checked like in the other versions, throwing the reason a file is not a usable ROM
+    pub fn load_rom(&mut self, name: &str, rom: &[u8]) -> Result<(), JsValue> {
+        let rom = RomLoader::new().load_bytes(name, rom.to_vec()).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.chip.insert_rom(rom.data).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.chip.reset();
+        Ok(())
+    }
+
+    pub fn step_frame(&mut self) {
+        self.chip.step_frame();
+    }
+
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        if down { self.chip.key_down(key) } else { self.chip.key_up(key) }
+    }
+
+    // 64x32 bytes, row by row, 1 for a lit pixel
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.chip.display.screen.concat()
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.chip.cpu.sound > 0
+    }
+}
+
+// For the file picker's accept attribute, like ".ch8,.c8"
+#[wasm_bindgen]
+pub fn rom_extensions() -> String {
+    EXTENSIONS.map(|extension| format!(".{}", extension)).join(",")
+}
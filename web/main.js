@@ -0,0 +1,59 @@
+import init, { Emulator, rom_extensions } from "./pkg/chip8_wasm.js";
+
+const KEYMAP = {
+    "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC,
+    "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+    "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE,
+    "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF,
+};
+
+await init();
+
+const emulator = new Emulator(Math.floor(Math.random() * 0xFFFFFFFF));
+document.getElementById("rom").accept = rom_extensions();
+const canvas = document.getElementById("screen");
+const ctx = canvas.getContext("2d");
+const image = ctx.createImageData(64, 32);
+let running = false;
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+    const file = event.target.files[0];
+    if (!file) return;
+    try {
+        emulator.load_rom(file.name, new Uint8Array(await file.arrayBuffer()));
+        running = true;
+    } catch (error) {
+        alert(error);
+    }
+});
+
+for (const [type, down] of [["keydown", true], ["keyup", false]]) {
+    document.addEventListener(type, (event) => {
+        const key = KEYMAP[event.key.toLowerCase()];
+        if (key !== undefined && !event.repeat) emulator.set_key(key, down);
+    });
+}
+
+// Displays refresh faster than 60 Hz on some monitors, so frames are paced by time
+const FRAME_MS = 1000 / 60;
+let last = performance.now();
+let accumulator = 0;
+
+function frame(now) {
+    accumulator = Math.min(accumulator + now - last, FRAME_MS * 4);
+    last = now;
+    if (running && accumulator >= FRAME_MS) {
+        while (accumulator >= FRAME_MS) {
+            emulator.step_frame();
+            accumulator -= FRAME_MS;
+        }
+        const framebuffer = emulator.framebuffer();
+        for (let i = 0; i < framebuffer.length; i++) {
+            const value = framebuffer[i] ? 255 : 0;
+            image.data.set([value, value, value, 255], i * 4);
+        }
+        ctx.putImageData(image, 0, 0);
+    }
+    requestAnimationFrame(frame);
+}
+requestAnimationFrame(frame);